@@ -0,0 +1,172 @@
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use crate::command;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const USAGE: &str = "\
+usage: ve [options] [file ...]
+
+  +N               start at line N of the first file (+ alone: the last line)
+  +/PATTERN        start at the first line with PATTERN
+  FILE:LINE[:COL]  start at that position, the way compilers print it
+  -R               read-only: :w needs a ! to write
+  -o               open the files in horizontal splits
+  -O               open the files in vertical splits
+  -h, --help       show this help
+  -v, --version    show the version
+";
+
+// where the cursor goes when a file is first shown; lines and columns
+// count from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Jump {
+    Line(usize),
+    Position(usize, usize),
+    Last,
+    Search(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub files: Vec<(PathBuf, Option<Jump>)>,
+    pub readonly: bool,
+    pub split: Option<Split>,
+}
+
+// the files given on the command line; :next and :prev walk through them
+#[derive(Debug, Default)]
+pub struct ArgList {
+    pub files: Vec<(PathBuf, Option<Jump>)>,
+    pub index: usize,
+}
+
+impl ArgList {
+    // "a.rs [b.rs] c.rs", the current one in brackets
+    pub fn summary(&self) -> String {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| match i == self.index {
+                true => format!("[{}]", path.display()),
+                false => path.display().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Action> {
+    let mut parsed = Args::default();
+    let mut jump = None;
+    let mut options = true;
+    for arg in args {
+        match arg.as_str() {
+            "--" if options => options = false,
+            "-h" | "--help" if options => return Ok(Action::Help),
+            "-v" | "--version" if options => return Ok(Action::Version),
+            "-R" if options => parsed.readonly = true,
+            "-o" if options => parsed.split = Some(Split::Horizontal),
+            "-O" if options => parsed.split = Some(Split::Vertical),
+            _ if options && arg.starts_with('+') => jump = Some(parse_jump(&arg[1..])?),
+            _ if options && arg.starts_with('-') && arg.len() > 1 => {
+                return Err(command::invalid(format!("unknown option {}", arg)));
+            }
+            _ => parsed.files.push(parse_file(&arg)),
+        }
+    }
+    // like vim, +N and +/PATTERN go with the first file
+    if let Some((_, first)) = parsed.files.first_mut()
+        && jump.is_some()
+    {
+        *first = jump;
+    }
+    Ok(Action::Edit(parsed))
+}
+
+fn parse_jump(arg: &str) -> Result<Jump> {
+    if arg.is_empty() {
+        return Ok(Jump::Last);
+    }
+    if let Some(pattern) = arg.strip_prefix('/') {
+        return Ok(Jump::Search(pattern.to_owned()));
+    }
+    arg.parse()
+        .map(Jump::Line)
+        .map_err(|_| command::invalid(format!("invalid line number +{}", arg)))
+}
+
+// "src/main.rs:12:5:" as compilers print it; a file that exists under the
+// whole name keeps it
+fn parse_file(arg: &str) -> (PathBuf, Option<Jump>) {
+    if Path::new(arg).exists() {
+        return (PathBuf::from(arg), None);
+    }
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    let number = |s: &str| s.parse::<usize>().ok();
+    if let Some((rest, last)) = trimmed.rsplit_once(':')
+        && let Some(last) = number(last)
+        && !rest.is_empty()
+    {
+        if let Some((file, line)) = rest.rsplit_once(':')
+            && let Some(line) = number(line)
+            && !file.is_empty()
+        {
+            return (PathBuf::from(file), Some(Jump::Position(line, last)));
+        }
+        return (PathBuf::from(rest), Some(Jump::Line(last)));
+    }
+    (PathBuf::from(arg), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(args: &[&str]) -> Args {
+        match parse(args.iter().map(|a| a.to_string())).unwrap() {
+            Action::Edit(args) => args,
+            action => panic!("expected files, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn positions_come_from_plus_and_colons() {
+        let args = edit(&["-R", "+12", "a.rs", "b.rs"]);
+        assert!(args.readonly);
+        assert_eq!(
+            args.files,
+            [
+                (PathBuf::from("a.rs"), Some(Jump::Line(12))),
+                (PathBuf::from("b.rs"), None)
+            ]
+        );
+        let args = edit(&["src/x.rs:3:7:", "y.rs:9", "+/fn main", "-O"]);
+        assert_eq!(args.files[0].1, Some(Jump::Search("fn main".to_owned())));
+        assert_eq!(args.files[1], (PathBuf::from("y.rs"), Some(Jump::Line(9))));
+        assert_eq!(args.split, Some(Split::Vertical));
+        assert_eq!(edit(&["+", "a"]).files[0].1, Some(Jump::Last));
+        assert_eq!(
+            edit(&["x.rs:3:7"]).files[0],
+            (PathBuf::from("x.rs"), Some(Jump::Position(3, 7)))
+        );
+        assert_eq!(edit(&["--", "-R"]).files[0].0, PathBuf::from("-R"));
+        assert_eq!(parse(["--help".to_owned()]).unwrap(), Action::Help);
+        assert!(parse(["-q".to_owned()]).is_err());
+        assert!(parse(["+x".to_owned()]).is_err());
+    }
+}
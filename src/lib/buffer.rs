@@ -3,6 +3,9 @@ use std::io::Result;
 use std::path::Path;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::display;
+use crate::encoding::{self, Encoding};
+
 #[derive(Default, Clone, Copy)]
 pub struct Location {
     pub x: usize,
@@ -64,23 +67,190 @@ impl Line {
     pub fn grapheme_len(&self) -> usize {
         self.graphemes.len().saturating_sub(1)
     }
+    // every grapheme with the display column it starts at and its width on
+    // screen, see display::cell_width
+    pub fn spans(&self, tabstop: usize) -> impl Iterator<Item = (&str, usize, usize)> + '_ {
+        let tabstop = tabstop.max(1);
+        let mut col = 0;
+        (0..self.grapheme_len()).map(move |i| {
+            let grapheme = self.grapheme_at(i).unwrap_or_default();
+            let width = display::cell_width(grapheme, col, tabstop);
+            col += width;
+            (grapheme, col - width, width)
+        })
+    }
+    pub fn grapheme_width(&self, i: usize, tabstop: usize) -> usize {
+        self.spans(tabstop).nth(i).map_or(0, |(_, _, width)| width)
+    }
+    // display column where grapheme i starts. passing i = grapheme_len()
+    // gives the display width of the whole line, which differs from the
+    // grapheme count once there are tabs or wide characters
+    pub fn column_of(&self, i: usize, tabstop: usize) -> usize {
+        self.spans(tabstop).take(i).map(|(_, _, width)| width).sum()
+    }
+    pub fn width(&self, tabstop: usize) -> usize {
+        self.column_of(self.grapheme_len(), tabstop)
+    }
+    // the grapheme drawn over display column col, or grapheme_len() past the
+    // end; the later cells of a wide character or tab map to that grapheme
+    pub fn grapheme_at_column(&self, col: usize, tabstop: usize) -> usize {
+        self.spans(tabstop)
+            .position(|(_, start, width)| col < start + width)
+            .unwrap_or(self.grapheme_len())
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::CrLf),
+            "mac" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+    // a file only counts as dos/mac if every line break agrees, otherwise the
+    // stray \r bytes stay part of the line so nothing is lost on write.
+    pub fn detect(contents: &str) -> Self {
+        let lf = contents.matches('\n').count();
+        let crlf = contents.matches("\r\n").count();
+        let cr = contents.matches('\r').count();
+        if lf > 0 && lf == crlf {
+            LineEnding::CrLf
+        } else if lf == 0 && cr > 0 {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+// one change to the text, as (line, grapheme) positions: what ran from start
+// to old_end now ends at new_end. other windows on the buffer move their
+// cursors along with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub start: (usize, usize),
+    pub old_end: (usize, usize),
+    pub new_end: (usize, usize),
+}
+
+impl Edit {
+    fn new(start: (usize, usize), old_end: (usize, usize), new_end: (usize, usize)) -> Self {
+        Self {
+            start,
+            old_end,
+            new_end,
+        }
+    }
+    // where text at pos ends up; text that was removed collapses to start
+    pub fn shift(&self, pos: (usize, usize)) -> (usize, usize) {
+        if pos < self.start {
+            pos
+        } else if pos < self.old_end {
+            self.start
+        } else if pos.0 == self.old_end.0 {
+            (self.new_end.0, pos.1 - self.old_end.1 + self.new_end.1)
+        } else {
+            (pos.0 - self.old_end.0 + self.new_end.0, pos.1)
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Buffer {
     pub lines: Vec<Line>,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub modified: bool,
+    pub changes: usize,
+    pub tabstop: usize,
+    pub shiftwidth: usize,
+    pub softtabstop: isize,
+    pub expandtab: bool,
+    pub readonly: bool,
+    // invalid utf-8 sequences that were read as raw byte chars; while set,
+    // to_bytes writes those chars back as the bytes they stand for
+    pub invalid: usize,
+    // the lowest line edited since the last take_changed, for the highlighter
+    changed_from: Option<usize>,
+    edits: Vec<Edit>,
 }
 
 impl Default for Buffer {
     fn default() -> Self {
         Self {
             lines: vec![Line::new()],
+            line_ending: LineEnding::default(),
+            final_newline: true,
+            encoding: Encoding::default(),
+            bom: false,
+            modified: false,
+            changes: 0,
+            tabstop: 8,
+            shiftwidth: 4,
+            softtabstop: -1,
+            expandtab: true,
+            readonly: false,
+            invalid: 0,
+            changed_from: Some(0),
+            edits: Vec::new(),
         }
     }
 }
 
 impl Buffer {
+    // like vim, a shiftwidth of 0 follows tabstop and a negative softtabstop
+    // follows shiftwidth
+    pub fn shift_width(&self) -> usize {
+        match self.shiftwidth {
+            0 => self.tabstop.max(1),
+            sw => sw,
+        }
+    }
+    pub fn soft_tab_width(&self) -> usize {
+        match self.softtabstop {
+            sts if sts < 0 => self.shift_width(),
+            sts => sts as usize,
+        }
+    }
+    fn mark_changed(&mut self, y: usize) {
+        self.modified = true;
+        self.changes += 1;
+        self.changed_from = Some(self.changed_from.map_or(y, |from| from.min(y)));
+    }
+    pub fn take_changed(&mut self) -> Option<usize> {
+        self.changed_from.take()
+    }
+    pub fn take_edits(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.edits)
+    }
     pub fn insert_char(&mut self, loc: &Location, c: char) {
+        self.mark_changed(loc.y);
         if self.lines.is_empty() {
             self.lines.push(Line::new());
         }
@@ -89,23 +259,142 @@ impl Buffer {
         }
         let line = &mut self.lines[loc.y];
         line.insert(loc.x, c);
+        let at = (loc.y, loc.x);
+        self.edits.push(Edit::new(at, at, (loc.y, loc.x + 1)));
     }
     pub fn delete_char(&mut self, loc: &Location) -> bool {
         if loc.y == 0 && loc.x == 0 {
             return false;
         }
+        self.mark_changed(if loc.x > 0 { loc.y } else { loc.y - 1 });
         if self.lines.is_empty() {
             self.lines.push(Line::new());
         }
         if loc.x > 0 {
             let line = &mut self.lines[loc.y];
             line.remove(loc.x - 1);
+            let start = (loc.y, loc.x - 1);
+            self.edits.push(Edit::new(start, (loc.y, loc.x), start));
         } else {
             let current_line = self.lines.remove(loc.y);
+            let start = (loc.y - 1, self.lines[loc.y - 1].grapheme_len());
             self.lines[loc.y - 1].push_str(&current_line.raw);
+            self.edits.push(Edit::new(start, (loc.y, 0), start));
         }
         true
     }
+    pub fn delete_forward(&mut self, loc: &Location) {
+        let line_len = self.lines[loc.y].grapheme_len();
+        let start = (loc.y, loc.x);
+        if loc.x < line_len {
+            self.lines[loc.y].remove(loc.x);
+            self.edits.push(Edit::new(start, (loc.y, loc.x + 1), start));
+        } else if loc.y + 1 < self.line_count() {
+            let next = self.lines.remove(loc.y + 1);
+            self.lines[loc.y].push_str(&next.raw);
+            self.edits.push(Edit::new(start, (loc.y + 1, 0), start));
+        } else {
+            return;
+        }
+        self.mark_changed(loc.y);
+    }
+    // whitespace covering display columns from..to: spaces with expandtab,
+    // otherwise as many tabs as fit and spaces for the rest
+    fn fill(&self, from: usize, to: usize) -> String {
+        let tabstop = self.tabstop.max(1);
+        let mut out = String::new();
+        let mut col = from;
+        while !self.expandtab && (col / tabstop + 1) * tabstop <= to {
+            out.push('\t');
+            col = (col / tabstop + 1) * tabstop;
+        }
+        out.extend(std::iter::repeat_n(' ', to.saturating_sub(col)));
+        out
+    }
+    fn replace_graphemes(&mut self, y: usize, from: usize, to: usize, text: &str) {
+        let line = &self.lines[y];
+        let (start, end) = (line.graphemes[from], line.graphemes[to]);
+        let mut raw = line.raw.clone();
+        raw.replace_range(start..end, text);
+        self.lines[y] = Line::from_string(raw);
+        self.mark_changed(y);
+        let new_end = (y, from + text.graphemes(true).count());
+        self.edits.push(Edit::new((y, from), (y, to), new_end));
+    }
+    // what the tab key inserts: a real tab, or whitespace up to the next
+    // softtabstop, merging the spaces just before the cursor into tabs when
+    // expandtab is off. returns the new cursor column.
+    pub fn insert_tab(&mut self, loc: &Location) -> usize {
+        let step = self.soft_tab_width();
+        if step == 0 && !self.expandtab {
+            self.insert_char(loc, '\t');
+            return loc.x + 1;
+        }
+        let step = if step == 0 { self.tabstop.max(1) } else { step };
+        let line = &self.lines[loc.y];
+        let spaces = (0..loc.x)
+            .rev()
+            .take_while(|&i| line.grapheme_at(i) == Some(" "))
+            .count();
+        let from = if self.expandtab {
+            loc.x
+        } else {
+            loc.x - spaces
+        };
+        let col = line.column_of(loc.x, self.tabstop);
+        let target = (col / step + 1) * step;
+        let text = self.fill(line.column_of(from, self.tabstop), target);
+        let len = text.graphemes(true).count();
+        self.replace_graphemes(loc.y, from, loc.x, &text);
+        from + len
+    }
+    // backspace over whitespace goes back to the previous softtabstop in one
+    // go; returns the new cursor column, or None for an ordinary backspace
+    pub fn delete_soft_tab(&mut self, loc: &Location) -> Option<usize> {
+        let step = self.soft_tab_width();
+        let line = &self.lines[loc.y];
+        let blank = |i: usize| matches!(line.grapheme_at(i), Some(" " | "\t"));
+        if step == 0 || loc.x == 0 || !blank(loc.x - 1) {
+            return None;
+        }
+        let from = (0..loc.x).rev().take_while(|&i| blank(i)).last()?;
+        let start = line.column_of(from, self.tabstop);
+        let col = line.column_of(loc.x, self.tabstop);
+        let stop = ((col - 1) / step * step).max(start);
+        let text = self.fill(start, stop);
+        let len = text.graphemes(true).count();
+        self.replace_graphemes(loc.y, from, loc.x, &text);
+        Some(from + len)
+    }
+    // moves the indent of line y to the next or previous multiple of
+    // shiftwidth; returns how many graphemes the indent grew or shrank by
+    pub fn shift_indent(&mut self, y: usize, forward: bool) -> isize {
+        let line = &self.lines[y];
+        let len = (0..line.grapheme_len())
+            .take_while(|&i| matches!(line.grapheme_at(i), Some(" " | "\t")))
+            .count();
+        let width = line.column_of(len, self.tabstop);
+        let sw = self.shift_width();
+        let target = if forward {
+            (width / sw + 1) * sw
+        } else {
+            width.saturating_sub(1) / sw * sw
+        };
+        let text = self.fill(0, target);
+        let new_len = text.graphemes(true).count();
+        self.replace_graphemes(y, 0, len, &text);
+        new_len as isize - len as isize
+    }
+    pub fn split_line(&mut self, loc: &Location) {
+        let line = &self.lines[loc.y];
+        let (left, right) = line.raw.split_at(line.graphemes[loc.x]);
+        let (left, right) = (left.to_owned(), right.to_owned());
+        self.lines[loc.y] = Line::from_string(left);
+        self.lines.insert(loc.y + 1, Line::from_string(right));
+        self.mark_changed(loc.y);
+        let at = (loc.y, loc.x);
+        self.edits.push(Edit::new(at, at, (loc.y + 1, 0)));
+    }
     pub fn line_at(&self, y: usize) -> &str {
         self.lines.get(y).map(|s| s.as_str()).unwrap_or("")
     }
@@ -117,24 +406,57 @@ impl Buffer {
     }
     pub fn buffer_to_string(&self) -> String {
         let mut out = self.lines.iter().map(|l| l.as_str()).collect::<Vec<_>>();
-        if let Some(last) = out.last() && last.is_empty() {
-                out.pop();
+        if !self.final_newline
+            && out.len() > 1
+            && let Some(last) = out.last()
+            && last.is_empty()
+        {
+            out.pop();
         }
-        out.join("\n")
+        let eol = self.line_ending.as_str();
+        let mut contents = out.join(eol);
+        if self.final_newline {
+            contents.push_str(eol);
+        }
+        contents
     }
-    pub fn read_file(&mut self, path: &Path) -> Result<()> {
-        let contents = fs::read_to_string(path)?;
-        let input = contents
-            .lines()
+    pub fn load_str(&mut self, contents: &str) {
+        self.line_ending = LineEnding::detect(contents);
+        let eol = self.line_ending.as_str();
+        let body = contents.strip_suffix(eol);
+        self.final_newline = body.is_some();
+        self.lines = body
+            .unwrap_or(contents)
+            .split(eol)
             .map(|l| Line::from_string(l.to_owned()))
-            .collect::<Vec<Line>>();
-        self.lines = input;
-        if contents.ends_with('\n') {
-            self.lines.push(Line::new());
+            .collect();
+        self.modified = false;
+        self.changes += 1;
+        self.changed_from = Some(0);
+    }
+    pub fn load_bytes(&mut self, bytes: &[u8]) {
+        let (contents, encoding, bom, invalid) = encoding::decode(bytes);
+        self.encoding = encoding;
+        self.bom = bom;
+        self.invalid = invalid;
+        self.load_str(&contents);
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        if self.bom {
+            bytes.extend_from_slice(self.encoding.bom());
         }
-        if self.lines.is_empty() {
-            self.lines.push(Line::new());
+        let contents = self.buffer_to_string();
+        if self.encoding == Encoding::Utf8 && self.invalid > 0 {
+            bytes.extend(encoding::encode_raw(&contents));
+        } else {
+            bytes.extend(self.encoding.encode(&contents)?);
         }
+        Ok(bytes)
+    }
+    pub fn read_file(&mut self, path: &Path) -> Result<()> {
+        let bytes = fs::read(path)?;
+        self.load_bytes(&bytes);
         Ok(())
     }
 }
@@ -143,6 +465,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[ignore = "delete_char removes the grapheme before the location, so x: 0 has nothing to remove"]
     fn insert_and_remove_single_char() {
         let mut buf = Buffer::default();
         // insert a char at the start
@@ -153,8 +476,20 @@ mod tests {
     }
 
     #[test]
-    fn buffer_to_string_ignores_final_empty_line() {
+    fn delete_char_removes_the_grapheme_before() {
         let mut buf = Buffer::default();
+        buf.insert_char(&Location { x: 0, y: 0 }, 'a');
+        assert!(!buf.delete_char(&Location { x: 0, y: 0 }));
+        assert!(buf.delete_char(&Location { x: 1, y: 0 }));
+        assert_eq!(buf.line_at(0), "");
+    }
+
+    #[test]
+    fn buffer_to_string_ignores_final_empty_line() {
+        let mut buf = Buffer {
+            final_newline: false,
+            ..Default::default()
+        };
         buf.insert_char(&Location { x: 0, y: 0 }, 'x');
         // add an empty line at the end explicitly
         buf.lines.push(Line::new());
@@ -164,6 +499,126 @@ mod tests {
     }
 
     #[test]
+    fn line_endings_round_trip() {
+        for contents in ["a\nb\n", "a\r\nb\r\n", "a\rb", "a\nb", "a\n\n", "", "\n"] {
+            let mut buf = Buffer::default();
+            buf.load_str(contents);
+            assert_eq!(buf.buffer_to_string(), contents);
+        }
+    }
+
+    #[test]
+    fn detects_line_ending_and_final_newline() {
+        let mut buf = Buffer::default();
+        buf.load_str("one\r\ntwo\r\n");
+        assert_eq!(buf.line_ending, LineEnding::CrLf);
+        assert!(buf.final_newline);
+        assert_eq!(buf.line_count(), 2);
+        assert_eq!(buf.line_at(1), "two");
+        // mixed endings fall back to unix and keep the stray \r in the line
+        buf.load_str("one\r\ntwo\n");
+        assert_eq!(buf.line_ending, LineEnding::Lf);
+        assert_eq!(buf.line_at(0), "one\r");
+    }
+
+    #[test]
+    fn converts_line_ending_on_write() {
+        let mut buf = Buffer::default();
+        buf.load_str("a\nb\n");
+        buf.line_ending = LineEnding::CrLf;
+        assert_eq!(buf.buffer_to_string(), "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn bytes_round_trip_with_encoding_and_bom() {
+        let inputs: [&[u8]; 3] = [
+            b"\xEF\xBB\xBFutf8 with bom\n",
+            b"\xFF\xFEa\0\r\0\n\0b\0\r\0\n\0",
+            b"caf\xE9 \x81\xFF\n",
+        ];
+        for bytes in inputs {
+            let mut buf = Buffer::default();
+            buf.load_bytes(bytes);
+            assert_eq!(buf.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn edits_mark_buffer_modified() {
+        let mut buf = Buffer::default();
+        buf.load_str("ab\ncd");
+        assert!(!buf.modified);
+        buf.split_line(&Location { x: 1, y: 0 });
+        assert!(buf.modified);
+        assert_eq!(buf.buffer_to_string(), "a\nb\ncd");
+        buf.delete_forward(&Location { x: 1, y: 1 });
+        assert_eq!(buf.buffer_to_string(), "a\nbcd");
+    }
+
+    #[test]
+    fn columns_follow_display_width() {
+        // wide cjk, a two-cell emoji, a combining accent, a zero-width space
+        // shown as <U+200B> and an escape shown as ^[
+        let line = Line::from_string("a漢😀e\u{301}\u{200b}\x1bb".to_owned());
+        assert_eq!(line.grapheme_len(), 7);
+        let columns = (0..=7).map(|i| line.column_of(i, 8)).collect::<Vec<_>>();
+        assert_eq!(columns, vec![0, 1, 3, 5, 6, 14, 16, 17]);
+        assert_eq!(line.width(8), 17);
+        let graphemes = (0..8)
+            .map(|c| line.grapheme_at_column(c, 8))
+            .collect::<Vec<_>>();
+        assert_eq!(graphemes, vec![0, 1, 1, 2, 2, 3, 4, 4]);
+    }
+
+    #[test]
+    fn tabs_reach_the_next_tabstop() {
+        let line = Line::from_string("a\tb\t\tc".to_owned());
+        let spans = line
+            .spans(4)
+            .map(|(_, col, w)| (col, w))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(0, 1), (1, 3), (4, 1), (5, 3), (8, 4), (12, 1)]);
+        assert_eq!(line.width(8), 25);
+        assert_eq!(line.grapheme_at_column(6, 4), 3);
+    }
+
+    #[test]
+    fn tab_key_follows_expandtab_and_softtabstop() {
+        let mut buffer = Buffer::default();
+        buffer.load_str("ab\n");
+        let x = buffer.insert_tab(&Location { x: 2, y: 0 });
+        assert_eq!((buffer.line_at(0), x), ("ab  ", 4));
+        buffer.expandtab = false;
+        // the spaces before the cursor merge into a tab at the tabstop
+        let x = buffer.insert_tab(&Location { x: 4, y: 0 });
+        assert_eq!((buffer.line_at(0), x), ("ab\t", 3));
+        assert_eq!(buffer.delete_soft_tab(&Location { x: 3, y: 0 }), Some(4));
+        assert_eq!(buffer.line_at(0), "ab  ");
+        assert_eq!(buffer.delete_soft_tab(&Location { x: 1, y: 0 }), None);
+        buffer.load_str("x       y\n");
+        assert_eq!(buffer.delete_soft_tab(&Location { x: 8, y: 0 }), Some(4));
+        assert_eq!(buffer.line_at(0), "x   y");
+        buffer.softtabstop = 0;
+        let x = buffer.insert_tab(&Location { x: 0, y: 0 });
+        assert_eq!((buffer.line_at(0), x), ("\tx   y", 1));
+    }
+
+    #[test]
+    fn shifting_rounds_the_indent_to_shiftwidth() {
+        let mut buffer = Buffer::default();
+        buffer.load_str("  code\n");
+        assert_eq!(buffer.shift_indent(0, true), 2);
+        assert_eq!(buffer.line_at(0), "    code");
+        buffer.expandtab = false;
+        buffer.shiftwidth = 0;
+        assert_eq!(buffer.shift_indent(0, true), -3);
+        assert_eq!(buffer.line_at(0), "\tcode");
+        assert_eq!(buffer.shift_indent(0, false), -1);
+        assert_eq!(buffer.line_at(0), "code");
+    }
+
+    #[test]
+    #[ignore = "a zwj emoji sequence is one extended grapheme cluster, not four"]
     fn grapheme_indices_are_correct() {
         let mut line = Line::new();
         line.push_str("👩‍❤️‍💋‍👨"); // complex emoji (4 graphemes)
@@ -173,4 +628,28 @@ mod tests {
         assert_eq!(line.grapheme_at(1).unwrap(), "‍❤️‍💋");
         assert_eq!(line.grapheme_at(2).unwrap(), "👨");
     }
+
+    #[test]
+    fn zwj_sequence_is_one_grapheme() {
+        let mut line = Line::new();
+        line.push_str("a👩‍❤️‍💋‍👨b");
+        assert_eq!(line.grapheme_len(), 3);
+        assert_eq!(line.grapheme_at(0).unwrap(), "a");
+        assert_eq!(line.grapheme_at(1).unwrap(), "👩‍❤️‍💋‍👨");
+        assert_eq!(line.grapheme_at(2).unwrap(), "b");
+    }
+
+    #[test]
+    fn edits_say_where_text_moved() {
+        let mut buffer = Buffer::default();
+        buffer.load_str("abc\ndef");
+        buffer.split_line(&Location { x: 1, y: 0 });
+        buffer.insert_char(&Location { x: 0, y: 1 }, 'x');
+        assert!(buffer.delete_char(&Location { x: 0, y: 2 }));
+        // a cursor on 'e', followed through all three edits
+        let pos = buffer.take_edits().iter().fold((1, 1), |p, e| e.shift(p));
+        assert_eq!(buffer.line_at(1), "xbcdef");
+        assert_eq!(pos, (1, 4));
+        assert!(buffer.take_edits().is_empty());
+    }
 }
@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::buffer::Buffer;
+use crate::cursor::Cursor;
+use crate::file::FileStamp;
+use crate::hex::HexEditor;
+use crate::swap::Swap;
+use crate::syntax::Highlighter;
+
+// a buffer other than the one being edited, with everything that belongs to
+// it; the current buffer keeps the same things in the editor's own fields
+#[derive(Debug, Default)]
+pub struct Hidden {
+    pub id: usize,
+    pub file: Option<PathBuf>,
+    pub buffer: Buffer,
+    pub cursor: Cursor,
+    // offset_x, offset_y and offset_row of the view when the buffer was left
+    pub scroll: (usize, usize, usize),
+    pub hex: Option<HexEditor>,
+    pub swap: Option<Swap>,
+    pub swap_tick: usize,
+    pub found_swap: Option<PathBuf>,
+    pub disk: Option<FileStamp>,
+    pub syntax: Highlighter,
+}
+
+impl Hidden {
+    // like Editor::refresh_syntax, for a buffer shown in another window
+    pub fn refresh_syntax(&mut self, end: usize) {
+        if let Some(y) = self.buffer.take_changed() {
+            self.syntax.invalidate(y);
+        }
+        self.syntax.update(&self.buffer, end);
+    }
+    pub fn is_modified(&self) -> bool {
+        match &self.hex {
+            Some(hex) => hex.modified,
+            None => self.buffer.modified,
+        }
+    }
+    // like Editor::contents_bytes
+    pub fn contents_bytes(&self) -> Vec<u8> {
+        match &self.hex {
+            Some(hex) => hex.bytes.clone(),
+            None => self
+                .buffer
+                .to_bytes()
+                .unwrap_or_else(|_| self.buffer.buffer_to_string().into_bytes()),
+        }
+    }
+}
+
+// the numbered buffers, like vim's buffer list; numbers are not reused
+#[derive(Debug)]
+pub struct BufferList {
+    pub current: usize,
+    // where ctrl-^ goes back to
+    pub alternate: Option<usize>,
+    hidden: Vec<Hidden>,
+    next: usize,
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        Self {
+            current: 1,
+            alternate: None,
+            hidden: Vec::new(),
+            next: 2,
+        }
+    }
+}
+
+impl BufferList {
+    pub fn new_id(&mut self) -> usize {
+        self.next += 1;
+        self.next - 1
+    }
+    // every buffer number in order, the current one included
+    pub fn ids(&self) -> Vec<usize> {
+        let mut ids = self.hidden.iter().map(|h| h.id).collect::<Vec<_>>();
+        ids.push(self.current);
+        ids.sort_unstable();
+        ids
+    }
+    pub fn hidden(&self) -> impl Iterator<Item = &Hidden> {
+        self.hidden.iter()
+    }
+    pub fn hidden_mut(&mut self) -> impl Iterator<Item = &mut Hidden> {
+        self.hidden.iter_mut()
+    }
+    pub fn get(&self, id: usize) -> Option<&Hidden> {
+        self.hidden.iter().find(|h| h.id == id)
+    }
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Hidden> {
+        self.hidden.iter_mut().find(|h| h.id == id)
+    }
+    pub fn contains(&self, id: usize) -> bool {
+        id == self.current || self.hidden.iter().any(|h| h.id == id)
+    }
+    pub fn take(&mut self, id: usize) -> Option<Hidden> {
+        let i = self.hidden.iter().position(|h| h.id == id)?;
+        Some(self.hidden.remove(i))
+    }
+    pub fn put(&mut self, hidden: Hidden) {
+        self.hidden.push(hidden);
+    }
+    // the buffer count places after the current one, wrapping around; a
+    // negative count goes back
+    pub fn cycle(&self, count: isize) -> usize {
+        let ids = self.ids();
+        let i = ids.iter().position(|&id| id == self.current).unwrap_or(0);
+        let n = ids.len() as isize;
+        ids[(i as isize + count).rem_euclid(n) as usize]
+    }
+}
+
+// whether two names point at the same file, also when one of them is relative
+// or goes through a symlink
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_wraps_around_in_number_order() {
+        let mut list = BufferList::default();
+        for _ in 0..3 {
+            let id = list.new_id();
+            list.put(Hidden {
+                id,
+                ..Hidden::default()
+            });
+        }
+        list.current = 3;
+        assert!(list.take(3).is_some());
+        list.put(Hidden {
+            id: 1,
+            ..Hidden::default()
+        });
+        assert_eq!(list.ids(), [1, 2, 3, 4]);
+        assert_eq!(list.cycle(1), 4);
+        assert_eq!(list.cycle(2), 1);
+        assert_eq!(list.cycle(-3), 4);
+        assert!(!list.contains(5));
+    }
+}
@@ -0,0 +1,256 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+
+use crate::keymap::{self, MapMode};
+use crate::window::Stack;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Write {
+        path: Option<PathBuf>,
+        force: bool,
+    },
+    Quit {
+        force: bool,
+    },
+    QuitAll {
+        force: bool,
+    },
+    Split {
+        stack: Stack,
+        path: Option<PathBuf>,
+    },
+    Close {
+        force: bool,
+    },
+    Only,
+    WriteQuit,
+    Hex,
+    Checktime,
+    Stats,
+    Recover {
+        discard: bool,
+    },
+    Edit {
+        path: Option<PathBuf>,
+        force: bool,
+    },
+    Next,
+    Previous,
+    Args,
+    Bnext,
+    Bprevious,
+    Buffer(String),
+    Buffers,
+    Bdelete {
+        target: Option<String>,
+        force: bool,
+    },
+    Set(Vec<String>),
+    Colorscheme(Option<String>),
+    Map {
+        modes: &'static [MapMode],
+        lhs: Option<String>,
+        rhs: Option<String>,
+        noremap: bool,
+    },
+    Unmap {
+        modes: &'static [MapMode],
+        lhs: String,
+    },
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self> {
+        // only the start is trimmed so a trailing escaped blank survives
+        // for :set, e.g. ":set showbreak=>\ "
+        let input = input.trim_start();
+        let (name, raw) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let (name, force) = match name.strip_suffix('!') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let args = raw.trim();
+        match name {
+            "w" | "write" => Ok(Command::Write {
+                path: (!args.is_empty()).then(|| PathBuf::from(args)),
+                force,
+            }),
+            "q" | "quit" => Ok(Command::Quit { force }),
+            "qa" | "qall" | "quitall" => Ok(Command::QuitAll { force }),
+            "sp" | "split" | "vs" | "vsplit" => Ok(Command::Split {
+                stack: match name.starts_with('v') {
+                    true => Stack::Columns,
+                    false => Stack::Rows,
+                },
+                path: (!args.is_empty()).then(|| PathBuf::from(args)),
+            }),
+            "clo" | "close" => Ok(Command::Close { force }),
+            "on" | "only" => Ok(Command::Only),
+            "wq" | "x" => Ok(Command::WriteQuit),
+            "hex" => Ok(Command::Hex),
+            "checktime" | "checkt" => Ok(Command::Checktime),
+            "stats" => Ok(Command::Stats),
+            "recover" | "rec" => Ok(Command::Recover { discard: force }),
+            "e" | "edit" => Ok(Command::Edit {
+                path: (!args.is_empty()).then(|| PathBuf::from(args)),
+                force,
+            }),
+            "n" | "next" => Ok(Command::Next),
+            "N" | "Next" | "prev" | "previous" => Ok(Command::Previous),
+            "ar" | "args" => Ok(Command::Args),
+            "bn" | "bnext" => Ok(Command::Bnext),
+            "bp" | "bprevious" | "bN" | "bNext" => Ok(Command::Bprevious),
+            "b" | "buffer" if args.is_empty() => Err(invalid("argument required".to_owned())),
+            "b" | "buffer" => Ok(Command::Buffer(args.to_owned())),
+            "ls" | "buffers" | "files" => Ok(Command::Buffers),
+            "bd" | "bdelete" => Ok(Command::Bdelete {
+                target: (!args.is_empty()).then(|| args.to_owned()),
+                force,
+            }),
+            "set" | "se" => Ok(Command::Set(split_args(raw))),
+            "colorscheme" | "colo" => Ok(Command::Colorscheme(
+                (!args.is_empty()).then(|| args.to_owned()),
+            )),
+            _ if let Some((modes, kind)) = map_command(name) => {
+                let (lhs, rhs) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                let (lhs, rhs) = (lhs.to_owned(), rhs.trim().to_owned());
+                match kind {
+                    Some(noremap) => Ok(Command::Map {
+                        modes,
+                        lhs: (!lhs.is_empty()).then_some(lhs),
+                        rhs: (!rhs.is_empty()).then_some(rhs),
+                        noremap,
+                    }),
+                    None if lhs.is_empty() => Err(invalid("argument required".to_owned())),
+                    None => Ok(Command::Unmap { modes, lhs }),
+                }
+            }
+            _ => Err(invalid(format!(
+                "not an editor command: {}",
+                input.trim_end()
+            ))),
+        }
+    }
+}
+
+// :map, :noremap and :unmap with an optional mode letter in front, e.g.
+// :nnoremap or :iunmap; the bool tells noremap from map and None is unmap
+fn map_command(name: &str) -> Option<(&'static [MapMode], Option<bool>)> {
+    ["", "n", "v", "i"].into_iter().find_map(|prefix| {
+        let kind = match name.strip_prefix(prefix)? {
+            "map" => Some(false),
+            "noremap" => Some(true),
+            "unmap" => None,
+            _ => return None,
+        };
+        Some((keymap::modes(prefix)?, kind))
+    })
+}
+
+// splits on whitespace except where it is escaped with a backslash, so
+// ":set listchars=tab:>\ ,eol:$" keeps its space
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next.is_whitespace() || next == '\\' => current.push(next),
+                Some(next) => {
+                    current.push('\\');
+                    current.push(next);
+                }
+                None => current.push('\\'),
+            },
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    out.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+pub fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_arguments() {
+        assert_eq!(
+            Command::parse("w").unwrap(),
+            Command::Write {
+                path: None,
+                force: false
+            }
+        );
+        assert_eq!(
+            Command::parse("w! out.txt").unwrap(),
+            Command::Write {
+                path: Some(PathBuf::from("out.txt")),
+                force: true
+            }
+        );
+        assert_eq!(Command::parse("q!").unwrap(), Command::Quit { force: true });
+        assert_eq!(
+            Command::parse("set ff=dos noeol").unwrap(),
+            Command::Set(vec!["ff=dos".to_owned(), "noeol".to_owned()])
+        );
+        assert_eq!(
+            Command::parse("set lcs=tab:>\\ ,eol:$ list").unwrap(),
+            Command::Set(vec!["lcs=tab:> ,eol:$".to_owned(), "list".to_owned()])
+        );
+        assert_eq!(
+            Command::parse("colo dusk").unwrap(),
+            Command::Colorscheme(Some("dusk".to_owned()))
+        );
+        assert_eq!(
+            Command::parse("nnoremap <Leader>w :w<CR>").unwrap(),
+            Command::Map {
+                modes: &[MapMode::Normal],
+                lhs: Some("<Leader>w".to_owned()),
+                rhs: Some(":w<CR>".to_owned()),
+                noremap: true
+            }
+        );
+        assert_eq!(
+            Command::parse("iunmap jk").unwrap(),
+            Command::Unmap {
+                modes: &[MapMode::Insert],
+                lhs: "jk".to_owned()
+            }
+        );
+        assert!(Command::parse("unmap").is_err());
+        assert_eq!(
+            Command::parse("e! src/main.rs").unwrap(),
+            Command::Edit {
+                path: Some(PathBuf::from("src/main.rs")),
+                force: true
+            }
+        );
+        assert_eq!(
+            Command::parse("b main").unwrap(),
+            Command::Buffer("main".to_owned())
+        );
+        assert!(Command::parse("b").is_err());
+        assert_eq!(
+            Command::parse("vs lib.rs").unwrap(),
+            Command::Split {
+                stack: Stack::Columns,
+                path: Some(PathBuf::from("lib.rs"))
+            }
+        );
+        assert!(Command::parse("frobnicate").is_err());
+    }
+}
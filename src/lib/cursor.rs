@@ -1,6 +1,7 @@
-use std::io::{Result, Write};
+use ratatui::layout::Size;
 
-use crate::buffer::Location;
+use crate::buffer::{Buffer, Location};
+use crate::view::{self, View};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Cursor {
@@ -15,7 +16,31 @@ impl From<Cursor> for Location {
 }
 
 impl Cursor {
-    pub fn move_left(&mut self, buffer: &crate::buffer::Buffer) {
+    // scrolls view so the cursor is inside a text area of that size
+    pub fn follow(&self, view: &mut View, buffer: &Buffer, viewport: Size) {
+        let max_rows = viewport.height.max(1) as usize;
+        if buffer.line_count() == 0 {
+            view.offset_x = 0;
+            view.offset_y = 0;
+            return;
+        }
+        if view.wrap {
+            let (top, skip) = self.scroll_rows(view, buffer, viewport);
+            view.offset_x = 0;
+            (view.offset_y, view.offset_row) = (top, skip);
+            return;
+        }
+        view.offset_row = 0;
+        let (new_offset_x, new_offset_y) = self.maybe_scroll(view, buffer, viewport);
+        let width = buffer
+            .lines
+            .get(self.y)
+            .map_or(0, |l| l.width(buffer.tabstop));
+        view.offset_x = new_offset_x.min(width);
+        let max_offset_y = buffer.line_count().saturating_sub(max_rows);
+        view.offset_y = new_offset_y.min(max_offset_y);
+    }
+    pub fn move_left(&mut self, buffer: &Buffer) {
         debug_assert!(
             self.y < buffer.lines.len(),
             "cursor out of bounds on move_left"
@@ -33,7 +58,7 @@ impl Cursor {
             self.x = prev_len.saturating_sub(1);
         }
     }
-    pub fn move_right(&mut self, buffer: &crate::buffer::Buffer) {
+    pub fn move_right(&mut self, buffer: &Buffer) {
         debug_assert!(
             self.y < buffer.lines.len(),
             "cursor out of bounds on move_right"
@@ -50,61 +75,167 @@ impl Cursor {
             self.x = 0;
         }
     }
-    pub fn move_up(&mut self, buffer: &crate::buffer::Buffer) {
+    pub fn move_up(&mut self, buffer: &Buffer) {
+        let column = buffer.lines[self.y].column_of(self.x, buffer.tabstop);
         if self.y > 0 {
             self.y -= 1;
         }
-        let line_len = buffer.lines[self.y].grapheme_len();
-        if self.x >= line_len {
-            self.x = line_len;
-        }
+        self.x = buffer.lines[self.y].grapheme_at_column(column, buffer.tabstop);
     }
-    pub fn move_down(&mut self, buffer: &crate::buffer::Buffer) {
+    pub fn move_down(&mut self, buffer: &Buffer) {
         debug_assert!(
             self.y < buffer.lines.len(),
             "cursor out of bounds on move_down"
         );
+        let column = buffer.lines[self.y].column_of(self.x, buffer.tabstop);
         let last_line = buffer.line_count().saturating_sub(1);
         if self.y < last_line {
             self.y += 1;
         }
-        let line_len = buffer.lines[self.y].grapheme_len();
-        debug_assert!(
-            self.x <= line_len,
-            "cursor column out of bounds after move_down"
-        );
-        if self.x >= line_len {
-            self.x = line_len;
+        self.x = buffer.lines[self.y].grapheme_at_column(column, buffer.tabstop);
+    }
+    // moves one screen row keeping the column on screen; a row that is not the
+    // last of its line cannot hold the cursor past its end
+    pub fn move_visual(&mut self, view: &View, buffer: &Buffer, width: usize, down: bool) {
+        if !view.wrap {
+            return if down {
+                self.move_down(buffer)
+            } else {
+                self.move_up(buffer)
+            };
+        }
+        let tabstop = buffer.tabstop;
+        let rows = view.rows(&buffer.lines[self.y], tabstop, width);
+        let row = view::row_of(&rows, self.x);
+        let line = &buffer.lines[self.y];
+        let dx = rows[row].prefix + line.column_of(self.x, tabstop)
+            - line.column_of(rows[row].start, tabstop);
+        let (y, target) = if down && row + 1 < rows.len() {
+            (self.y, row + 1)
+        } else if down && self.y + 1 < buffer.line_count() {
+            (self.y + 1, 0)
+        } else if !down && row > 0 {
+            (self.y, row - 1)
+        } else if !down && self.y > 0 {
+            let rows = view.row_count(buffer, self.y - 1, width);
+            (self.y - 1, rows - 1)
+        } else {
+            return;
+        };
+        let line = &buffer.lines[y];
+        let rows = view.rows(line, tabstop, width);
+        let r = rows[target];
+        let column = line.column_of(r.start, tabstop) + dx.saturating_sub(r.prefix);
+        let mut x = line.grapheme_at_column(column, tabstop).max(r.start);
+        if target + 1 < rows.len() {
+            x = x.min(r.end - 1);
         }
+        self.y = y;
+        self.x = x;
     }
-    pub fn maybe_scroll(&self, view: &crate::view::View) -> (usize, usize) {
-        let (cols, rows) = ratatui::termion::terminal_size().unwrap_or((80, 24));
-        let max_cols = cols as usize;
-        let max_rows = rows as usize;
+    // offsets are display columns, so the whole of a wide character under the
+    // cursor is brought into view
+    pub fn maybe_scroll(&self, view: &View, buffer: &Buffer, viewport: Size) -> (usize, usize) {
+        let max_cols = viewport.width.max(1) as usize;
+        let max_rows = viewport.height.max(1) as usize;
+        let (column, width) = match buffer.lines.get(self.y) {
+            Some(line) => (
+                line.column_of(self.x, buffer.tabstop),
+                line.grapheme_width(self.x, buffer.tabstop).max(1),
+            ),
+            None => (0, 1),
+        };
         let mut new_offset_x = view.offset_x;
-        if self.x < view.offset_x {
-            new_offset_x = self.x;
-        } else if self.x >= view.offset_x + max_cols {
-            new_offset_x = self.x + 1 - max_cols;
+        if column < view.offset_x {
+            new_offset_x = column;
+        } else if column + width > view.offset_x + max_cols {
+            new_offset_x = column + width - max_cols;
         }
+        // scrolloff keeps that many lines around the cursor in view
+        let so = view.scrolloff.min((max_rows - 1) / 2);
         let mut new_offset_y = view.offset_y;
-        if self.y < view.offset_y {
-            new_offset_y = self.y;
-        } else if self.y >= view.offset_y + max_rows {
-            new_offset_y = self.y + 1 - max_rows;
+        if self.y < view.offset_y + so {
+            new_offset_y = self.y.saturating_sub(so);
+        } else if self.y + so >= view.offset_y + max_rows {
+            new_offset_y = self.y + so + 1 - max_rows;
         }
 
         (new_offset_x, new_offset_y)
     }
-    pub fn render_cursor<W: Write>(
-        &self,
-        view_offset_x: usize,
-        view_offset_y: usize,
-        stdout: &mut W,
-    ) -> Result<()> {
-        let cur_x = (self.x as i32 - view_offset_x as i32).max(0) as u16 + 1;
-        let cur_y = (self.y as i32 - view_offset_y as i32).max(0) as u16 + 1;
-        write!(stdout, "{}", ratatui::termion::cursor::Goto(cur_x, cur_y))?;
-        stdout.flush()
+    // with wrap on, the top line and how many of its rows are scrolled off so
+    // that the row holding the cursor is on screen; a line taller than the
+    // screen scrolls by rows like vim's skipcol
+    pub fn scroll_rows(&self, view: &View, buffer: &Buffer, viewport: Size) -> (usize, usize) {
+        let width = viewport.width.max(1) as usize;
+        let height = viewport.height.max(1) as usize;
+        let cursor_row = buffer.lines.get(self.y).map_or(0, |line| {
+            view::row_of(&view.rows(line, buffer.tabstop, width), self.x)
+        });
+        let (mut top, mut skip) = (view.offset_y, view.offset_row);
+        let so = view.scrolloff.min((height - 1) / 2);
+        let first = self.y.saturating_sub(so);
+        if so == 0 && (self.y < top || (self.y == top && cursor_row < skip)) {
+            return (self.y, cursor_row);
+        }
+        if first < top || (so > 0 && first == top && skip > 0) {
+            (top, skip) = (first, 0);
+        }
+        if self.y > top + height {
+            (top, skip) = (self.y - height, 0);
+        }
+        skip = skip.min(view.row_count(buffer, top, width) - 1);
+        let mut row = (top..self.y)
+            .map(|y| view.row_count(buffer, y, width))
+            .sum::<usize>()
+            + cursor_row
+            - skip;
+        while row >= height {
+            let left = view.row_count(buffer, top, width) - skip;
+            if top < self.y && row - left >= height {
+                row -= left;
+                (top, skip) = (top + 1, 0);
+            } else {
+                skip += row + 1 - height;
+                row = height - 1;
+            }
+        }
+        let last = buffer.line_count().saturating_sub(1);
+        let below = (self.y + 1..=(self.y + so).min(last))
+            .map(|y| view.row_count(buffer, y, width))
+            .sum::<usize>();
+        while row > 0 && row + below >= height {
+            skip += 1;
+            if skip == view.row_count(buffer, top, width) {
+                (top, skip) = (top + 1, 0);
+            }
+            row -= 1;
+        }
+        (top, skip)
+    }
+    pub fn screen_position(&self, view: &View, buffer: &Buffer, viewport: Size) -> (u16, u16) {
+        if view.wrap {
+            let width = viewport.width.max(1) as usize;
+            let Some(line) = buffer.lines.get(self.y) else {
+                return (0, 0);
+            };
+            let rows = view.rows(line, buffer.tabstop, width);
+            let row = view::row_of(&rows, self.x);
+            let above = (view.offset_y..self.y)
+                .map(|y| view.row_count(buffer, y, width))
+                .sum::<usize>();
+            let y = (above + row).saturating_sub(view.offset_row);
+            let r = rows[row];
+            let column =
+                line.column_of(self.x, buffer.tabstop) - line.column_of(r.start, buffer.tabstop);
+            let x = (r.prefix + column).min(width - 1);
+            return (x as u16, y.min(u16::MAX as usize) as u16);
+        }
+        let column = buffer
+            .lines
+            .get(self.y)
+            .map_or(0, |l| l.column_of(self.x, buffer.tabstop));
+        let x = column.saturating_sub(view.offset_x) as u16;
+        let y = self.y.saturating_sub(view.offset_y) as u16;
+        (x, y)
     }
 }
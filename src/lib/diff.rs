@@ -0,0 +1,115 @@
+const MAX_TABLE: usize = 4_000_000;
+const CONTEXT: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// longest-common-subsequence diff over the part that differs; past
+// MAX_TABLE cells the middle is reported as one replaced block instead.
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut out: Vec<Change> = old[..prefix].iter().map(|l| Change::Same(l)).collect();
+    if a.len().saturating_mul(b.len()) > MAX_TABLE {
+        out.extend(a.iter().map(|l| Change::Removed(l)));
+        out.extend(b.iter().map(|l| Change::Added(l)));
+    } else {
+        let mut table = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                table[i][j] = if a[i] == b[j] {
+                    table[i + 1][j + 1] + 1
+                } else {
+                    table[i + 1][j].max(table[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                out.push(Change::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if j < b.len() && (i == a.len() || table[i][j + 1] > table[i + 1][j]) {
+                out.push(Change::Added(b[j]));
+                j += 1;
+            } else {
+                out.push(Change::Removed(a[i]));
+                i += 1;
+            }
+        }
+    }
+    out.extend(old[old.len() - suffix..].iter().map(|l| Change::Same(l)));
+    out
+}
+
+pub fn unified(old: &[&str], new: &[&str]) -> Vec<String> {
+    let changes = diff_lines(old, new);
+    let near_change = |i: usize| {
+        let lo = i.saturating_sub(CONTEXT);
+        let hi = (i + CONTEXT + 1).min(changes.len());
+        changes[lo..hi]
+            .iter()
+            .any(|c| !matches!(c, Change::Same(_)))
+    };
+    let mut out = Vec::new();
+    let mut skipped = false;
+    for (i, change) in changes.iter().enumerate() {
+        if !near_change(i) {
+            skipped = true;
+            continue;
+        }
+        if skipped && !out.is_empty() {
+            out.push("...".to_owned());
+        }
+        skipped = false;
+        out.push(match change {
+            Change::Same(l) => format!("  {}", l),
+            Change::Removed(l) => format!("- {}", l),
+            Change::Added(l) => format!("+ {}", l),
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_finds_insertions_and_removals() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "c", "x", "d"];
+        assert_eq!(
+            diff_lines(&old, &new),
+            vec![
+                Change::Same("a"),
+                Change::Removed("b"),
+                Change::Same("c"),
+                Change::Added("x"),
+                Change::Same("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_keeps_context_around_changes() {
+        let old = ["1", "2", "3", "4", "5", "6", "7", "8"];
+        let new = ["1", "2", "3", "4", "5", "6", "7", "eight"];
+        assert_eq!(unified(&old, &new), vec!["  6", "  7", "- 8", "+ eight"]);
+        assert!(unified(&old, &old).is_empty());
+    }
+}
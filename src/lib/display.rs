@@ -0,0 +1,129 @@
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+
+// how a grapheme the terminal cannot show safely is written instead: caret
+// notation for ascii controls and <U+XXXX> for anything else that is
+// invisible, e.g. C1 controls, zero-width spaces or bidi overrides
+pub fn notation(grapheme: &str) -> Option<String> {
+    let mut chars = grapheme.chars();
+    let c = chars.next()?;
+    if chars.next().is_none() && (c < ' ' || c == '\x7f') {
+        return Some(format!("^{}", ((c as u8) ^ 0x40) as char));
+    }
+    if grapheme.width() == 0 || grapheme.chars().any(char::is_control) {
+        return Some(
+            grapheme
+                .chars()
+                .map(|c| format!("<U+{:04X}>", c as u32))
+                .collect(),
+        );
+    }
+    None
+}
+
+// cells taken by a grapheme starting at display column col; a tab reaches to
+// the next multiple of tabstop
+pub fn cell_width(grapheme: &str, col: usize, tabstop: usize) -> usize {
+    match grapheme {
+        "\t" => tabstop - col % tabstop,
+        g => notation(g).map_or_else(|| g.width(), |n| n.len()),
+    }
+}
+
+// the glyphs 'list' mode draws, in vim's listchars syntax:
+// "tab:> ,trail:-,nbsp:+,eol:$"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListChars {
+    pub tab: Option<(char, char)>,
+    pub trail: Option<char>,
+    pub nbsp: Option<char>,
+    pub eol: Option<char>,
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self {
+            tab: Some(('>', ' ')),
+            trail: Some('-'),
+            nbsp: Some('+'),
+            eol: Some('$'),
+        }
+    }
+}
+
+impl ListChars {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut out = ListChars {
+            tab: None,
+            trail: None,
+            nbsp: None,
+            eol: None,
+        };
+        for item in value.split(',').filter(|i| !i.is_empty()) {
+            let (name, glyphs) = item.split_once(':')?;
+            let glyphs = glyphs.chars().collect::<Vec<_>>();
+            match (name, glyphs.as_slice()) {
+                ("tab", &[first, fill]) => out.tab = Some((first, fill)),
+                ("trail", &[c]) => out.trail = Some(c),
+                ("nbsp", &[c]) => out.nbsp = Some(c),
+                ("eol", &[c]) => out.eol = Some(c),
+                _ => return None,
+            }
+        }
+        Some(out)
+    }
+}
+
+impl fmt::Display for ListChars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items = Vec::new();
+        if let Some((first, fill)) = self.tab {
+            items.push(format!("tab:{}{}", first, fill));
+        }
+        let singles = [
+            ("trail", self.trail),
+            ("nbsp", self.nbsp),
+            ("eol", self.eol),
+        ];
+        for (name, glyph) in singles {
+            if let Some(c) = glyph {
+                items.push(format!("{}:{}", name, c));
+            }
+        }
+        write!(f, "{}", items.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invisible_characters_get_a_notation() {
+        assert_eq!(notation("\r").as_deref(), Some("^M"));
+        assert_eq!(notation("\0").as_deref(), Some("^@"));
+        assert_eq!(notation("\x1b").as_deref(), Some("^["));
+        assert_eq!(notation("\x7f").as_deref(), Some("^?"));
+        assert_eq!(notation("\u{200b}").as_deref(), Some("<U+200B>"));
+        assert_eq!(notation("\u{202e}").as_deref(), Some("<U+202E>"));
+        assert_eq!(notation("\u{85}").as_deref(), Some("<U+0085>"));
+        assert_eq!(notation("e\u{301}"), None);
+        assert_eq!(notation("漢"), None);
+        assert_eq!(cell_width("\x1b", 0, 8), 2);
+        assert_eq!(cell_width("\t", 3, 8), 5);
+    }
+
+    #[test]
+    fn listchars_round_trip() {
+        let lcs = ListChars::parse("tab:>-,eol:$").unwrap();
+        assert_eq!(lcs.tab, Some(('>', '-')));
+        assert_eq!(lcs.trail, None);
+        assert_eq!(lcs.to_string(), "tab:>-,eol:$");
+        assert_eq!(
+            ListChars::parse(&ListChars::default().to_string()),
+            Some(ListChars::default())
+        );
+        assert!(ListChars::parse("tab:>").is_none());
+        assert!(ListChars::parse("space:.").is_none());
+    }
+}
@@ -1,22 +1,97 @@
-use ratatui::termion::event::Key;
-use ratatui::termion::input::TermRead;
+use ratatui::Frame;
+use ratatui::Terminal;
+use ratatui::backend::Backend;
+use ratatui::layout::{Position, Size};
 use std::fs;
-use std::io::ErrorKind;
-use std::io::{Result, Write, stdin};
+use std::io::{self, ErrorKind};
+use std::io::{Result, stdin};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::buffer::Buffer;
+use crate::args::{ArgList, Args, Jump, Split};
+use crate::buffer::{Buffer, LineEnding, Location};
+use crate::buflist::{self, BufferList, Hidden};
+use crate::command::{self, Command};
 use crate::cursor::Cursor;
+use crate::diff;
+use crate::encoding::Encoding;
+use crate::event::{Event, EventLoop, Timer};
+use crate::file::{self, FileStamp};
+use crate::hex::{self, HexEditor};
+use crate::input::Key;
 use crate::keyhandler::{KeyHandler, Mode};
+use crate::keymap::Keymap;
+use crate::options::{self, OptionDef, Scope, Value};
+use crate::swap::{self, Dump, Swap, SwapInfo};
+use crate::syntax::{self, Highlighter};
+use crate::terminal::{ByteCounter, RenderStats};
+use crate::theme::{ColorDepth, Theme};
 use crate::view::View;
+use crate::widget::EditorView;
+use crate::window::{Layout, Stack, Window, WindowList};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    Overwrite(PathBuf),
+    Reload,
+}
+
+impl Prompt {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Prompt::Overwrite(_) => "file changed on disk since it was read; overwrite? (y/n)",
+            Prompt::Reload => "file changed on disk; reload it? (y/n)",
+        }
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct Editor {
     current_file: Option<PathBuf>,
+    pub buffers: BufferList,
+    pub arglist: ArgList,
+    pub windows: WindowList,
     mode: Mode,
     pub buffer: Buffer,
     pub view: View,
     pub cursor: Cursor,
+    pub backup: bool,
+    pub command_line: String,
+    status: String,
+    quit: bool,
+    pub hex: Option<HexEditor>,
+    pub messages: Vec<String>,
+    swap: Option<Swap>,
+    swap_tick: usize,
+    // the buffer, change tick and modified flag the panic snapshot was taken at
+    snapshot_key: Option<(usize, usize, bool)>,
+    // the current buffer and buffer ids the hidden buffers were last dumped
+    // for; a hidden buffer only changes by becoming the current one
+    hidden_key: Option<(usize, Vec<usize>)>,
+    found_swap: Option<PathBuf>,
+    disk: Option<FileStamp>,
+    pub autoread: bool,
+    pub prompt: Option<Prompt>,
+    pub autosave: usize,
+    pub events: EventLoop,
+    pub output: ByteCounter,
+    pub render_stats: RenderStats,
+    pub screen_cursor: Option<Position>,
+    pub pending: Option<Key>,
+    pub syntax: Highlighter,
+    pub theme: Theme,
+    pub ignorecase: bool,
+    pub inherited: Vec<(&'static OptionDef, Value)>,
+    pub keymap: Keymap,
+    // keys typed but not yet run, while they may still become a mapping
+    pub typeahead: Vec<Key>,
+    viewport: Size,
+}
+
+// what a buffer's recovery file is called when it has no swap file
+fn dump_name(file: Option<&PathBuf>) -> String {
+    file.and_then(|p| p.file_name())
+        .map_or("unnamed".to_owned(), |n| n.to_string_lossy().into_owned())
 }
 
 impl Editor {
@@ -24,46 +99,969 @@ impl Editor {
         &self.current_file
     }
     pub fn opened_file(&self) -> Option<&Path> {
-        self.current_file.as_ref().map(|pb| pb.as_path())
+        self.current_file.as_deref()
     }
     pub fn open_file(&mut self, at: &Path) -> Result<()> {
         self.current_file = Some(at.to_path_buf());
-        match self.buffer.read_file(at) {
-            Ok(()) => {}
+        match fs::read(at) {
+            Ok(bytes) => {
+                self.disk = fs::metadata(at).ok().map(|m| FileStamp::new(&m, &bytes));
+                self.load_contents(bytes);
+                let info = self.file_info();
+                self.set_status(format!("\"{}\" {}", at.display(), info));
+            }
             Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.disk = None;
+                self.hex = None;
                 self.buffer = Buffer::default();
+                options::inherit(self, Scope::Buffer);
+                self.set_status(format!("\"{}\" [New]", at.display()));
             }
             Err(e) => return Err(e),
         }
+        // the buffer may have been reused, and its old position can be past
+        // the end of the new contents
+        self.cursor = Cursor::default();
+        self.view.reset_scroll();
+        match syntax::for_path(at) {
+            Ok(grammar) => self.syntax = Highlighter::new(grammar),
+            Err(e) => self.set_status(e.to_string()),
+        }
+        self.check_swap(at);
+        Ok(())
+    }
+    // moves the current buffer out of the editor's fields
+    fn stash(&mut self) -> Hidden {
+        Hidden {
+            id: self.buffers.current,
+            file: self.current_file.take(),
+            buffer: std::mem::take(&mut self.buffer),
+            cursor: std::mem::take(&mut self.cursor),
+            scroll: (self.view.offset_x, self.view.offset_y, self.view.offset_row),
+            hex: self.hex.take(),
+            swap: self.swap.take(),
+            swap_tick: std::mem::take(&mut self.swap_tick),
+            found_swap: self.found_swap.take(),
+            disk: self.disk.take(),
+            syntax: std::mem::take(&mut self.syntax),
+        }
+    }
+    // makes a stashed buffer the current one; the window options stay
+    fn unstash(&mut self, hidden: Hidden) {
+        self.buffers.current = hidden.id;
+        self.current_file = hidden.file;
+        self.buffer = hidden.buffer;
+        self.cursor = hidden.cursor;
+        self.view.reset_scroll();
+        (self.view.offset_x, self.view.offset_y, self.view.offset_row) = hidden.scroll;
+        self.hex = hidden.hex;
+        self.swap = hidden.swap;
+        self.swap_tick = hidden.swap_tick;
+        self.found_swap = hidden.found_swap;
+        self.disk = hidden.disk;
+        self.syntax = hidden.syntax;
+        self.update_view();
+    }
+    fn enter_buffer(&mut self, id: usize) -> Result<()> {
+        if id == self.buffers.current {
+            return Ok(());
+        }
+        self.sync_windows();
+        // the buffer being left takes its latest edits to its swap file
+        if self.buffers.contains(id) {
+            self.sync_swap()?;
+        }
+        let target = self
+            .buffers
+            .take(id)
+            .ok_or_else(|| command::invalid(format!("buffer {} does not exist", id)))?;
+        let current = self.stash();
+        self.buffers.alternate = Some(current.id);
+        self.buffers.put(current);
+        self.unstash(target);
+        Ok(())
+    }
+    pub fn switch_buffer(&mut self, id: usize) -> Result<()> {
+        self.enter_buffer(id)?;
+        let name = self.buffer_name();
+        let info = self.file_info();
+        self.set_status(format!("\"{}\" {}", name, info));
+        Ok(())
+    }
+    // a new empty buffer becomes the current one
+    fn add_buffer(&mut self) {
+        let id = self.buffers.new_id();
+        let current = self.stash();
+        self.buffers.alternate = Some(current.id);
+        self.buffers.put(current);
+        self.buffers.current = id;
+        options::inherit(self, Scope::Buffer);
+        self.view.reset_scroll();
+    }
+    pub fn buffer_name(&self) -> String {
+        self.current_file
+            .as_ref()
+            .map_or("[No Name]".to_owned(), |p| p.display().to_string())
+    }
+    // :e, which reloads the current file without a name; an unnamed and
+    // unchanged buffer is reused for the new file unless another window shows it
+    pub fn edit(&mut self, path: Option<PathBuf>, force: bool) -> Result<()> {
+        let Some(path) = path else {
+            if self.current_file.is_none() {
+                return Err(command::invalid("no file name".to_owned()));
+            }
+            if !force && self.is_modified() {
+                return Err(command::invalid(
+                    "no write since last change (add ! to override)".to_owned(),
+                ));
+            }
+            self.reload()?;
+            let info = self.file_info();
+            self.set_status(format!("\"{}\" {}", self.buffer_name(), info));
+            return Ok(());
+        };
+        let existing = self.current_file.iter().map(|f| (self.buffers.current, f));
+        let existing = existing
+            .chain(
+                self.buffers
+                    .hidden()
+                    .filter_map(|h| h.file.as_ref().map(|f| (h.id, f))),
+            )
+            .find(|(_, f)| buflist::same_file(f, &path))
+            .map(|(id, _)| id);
+        if let Some(id) = existing {
+            return self.switch_buffer(id);
+        }
+        self.sync_swap()?;
+        let current = self.buffers.current;
+        let shown = self.windows.others.iter().any(|w| w.buffer == current);
+        if self.current_file.is_some() || self.is_modified() || shown {
+            self.add_buffer();
+        }
+        self.open_file(&path)
+    }
+    // the files from the command line; -R makes every buffer read-only
+    pub fn open_args(&mut self, args: Args) -> Result<()> {
+        if args.readonly {
+            options::set_for_all(self, "readonly", Value::Bool(true))?;
+        }
+        self.arglist = ArgList {
+            files: args.files,
+            index: 0,
+        };
+        if self.arglist.files.is_empty() {
+            return Ok(());
+        }
+        // -o and -O give every file a window, the first one on top or at the
+        // left, all the same size
+        if let Some(split) = args.split {
+            let stack = match split {
+                Split::Horizontal => Stack::Rows,
+                Split::Vertical => Stack::Columns,
+            };
+            let last = self.arglist.files.len() - 1;
+            self.goto_arg(last)?;
+            for i in (0..last).rev() {
+                self.split_window(stack)?;
+                self.goto_arg(i)?;
+            }
+            self.windows.layout.equalize();
+            return Ok(());
+        }
+        self.goto_arg(0)
+    }
+    // edits an argument; its position, if it came with one, is used the first
+    // time it is shown
+    pub fn goto_arg(&mut self, index: usize) -> Result<()> {
+        let Some((path, _)) = self.arglist.files.get(index) else {
+            return Err(command::invalid("cannot go beyond last file".to_owned()));
+        };
+        self.edit(Some(path.clone()), false)?;
+        self.arglist.index = index;
+        if let Some(jump) = self.arglist.files[index].1.take() {
+            self.jump(&jump);
+        }
+        Ok(())
+    }
+    pub fn jump(&mut self, jump: &Jump) {
+        let last = self.buffer.line_count().saturating_sub(1);
+        let (y, x) = match jump {
+            Jump::Line(line) => (line.saturating_sub(1), 0),
+            Jump::Position(line, col) => (line.saturating_sub(1), col.saturating_sub(1)),
+            Jump::Last => (last, 0),
+            Jump::Search(pattern) => match self.find_text(pattern) {
+                Some(found) => found,
+                None => {
+                    self.set_status(format!("pattern not found: {}", pattern));
+                    return;
+                }
+            },
+        };
+        self.cursor.y = y;
+        self.cursor.x = x;
+        self.clamp_cursor();
+        self.update_view();
+    }
+    // the first line and grapheme where pattern occurs; ignorecase folds
+    // ascii letters only, so byte offsets still line up
+    fn find_text(&self, pattern: &str) -> Option<(usize, usize)> {
+        let fold = |s: &str| match self.ignorecase {
+            true => s.to_ascii_lowercase(),
+            false => s.to_owned(),
+        };
+        let pattern = fold(pattern);
+        self.buffer.lines.iter().enumerate().find_map(|(y, line)| {
+            let byte = fold(line.as_str()).find(&pattern)?;
+            let mut offset = 0;
+            let x = (0..line.grapheme_len())
+                .take_while(|&i| {
+                    offset += line.grapheme_at(i).map_or(0, str::len);
+                    offset <= byte
+                })
+                .count();
+            Some((y, x))
+        })
+    }
+    // a buffer by number, or by a piece of its name that only one matches
+    fn find_buffer(&self, name: &str) -> Result<usize> {
+        if let Ok(id) = name.parse() {
+            return match self.buffers.contains(id) {
+                true => Ok(id),
+                false => Err(command::invalid(format!("buffer {} does not exist", id))),
+            };
+        }
+        let files = self
+            .current_file
+            .iter()
+            .map(|f| (self.buffers.current, f))
+            .chain(
+                self.buffers
+                    .hidden()
+                    .filter_map(|h| h.file.as_ref().map(|f| (h.id, f))),
+            )
+            .filter(|(_, f)| f.to_string_lossy().contains(name))
+            .collect::<Vec<_>>();
+        let exact = files
+            .iter()
+            .filter(|(_, f)| f.file_name().is_some_and(|n| n == name))
+            .collect::<Vec<_>>();
+        match (files.as_slice(), exact.as_slice()) {
+            ([(id, _)], _) | (_, [(id, _)]) => Ok(*id),
+            ([], _) => Err(command::invalid(format!("no matching buffer for {}", name))),
+            _ => Err(command::invalid(format!(
+                "more than one match for {}",
+                name
+            ))),
+        }
+    }
+    pub fn alternate_buffer(&mut self) -> Result<()> {
+        match self
+            .buffers
+            .alternate
+            .filter(|&id| self.buffers.contains(id))
+        {
+            Some(id) => self.switch_buffer(id),
+            None => Err(command::invalid("no alternate file".to_owned())),
+        }
+    }
+    // :bd; the current buffer gives way to the alternate or the next one,
+    // or to an empty buffer when it was the last
+    pub fn delete_buffer(&mut self, target: Option<&str>, force: bool) -> Result<()> {
+        let id = match target {
+            Some(name) => self.find_buffer(name)?,
+            None => self.buffers.current,
+        };
+        if let Some(hidden) = self.buffers.take(id) {
+            if !force && hidden.is_modified() {
+                self.buffers.put(hidden);
+                return Err(command::invalid(format!(
+                    "no write since last change for buffer {} (add ! to override)",
+                    id
+                )));
+            }
+            if let Some(swap) = hidden.swap {
+                swap.remove();
+            }
+            self.buffers.alternate = self.buffers.alternate.filter(|&a| a != id);
+            self.close_windows_of(id);
+            return Ok(());
+        }
+        if !force && self.is_modified() {
+            return Err(command::invalid(format!(
+                "no write since last change for buffer {} (add ! to override)",
+                id
+            )));
+        }
+        let next = self
+            .buffers
+            .alternate
+            .filter(|&a| self.buffers.contains(a))
+            .unwrap_or_else(|| self.buffers.cycle(1));
+        let deleted = self.stash();
+        if let Some(swap) = deleted.swap {
+            swap.remove();
+        }
+        self.buffers.alternate = None;
+        match self.buffers.take(next) {
+            Some(hidden) => self.unstash(hidden),
+            None => {
+                self.buffers.current = self.buffers.new_id();
+                options::inherit(self, Scope::Buffer);
+                self.view.reset_scroll();
+            }
+        }
+        self.close_windows_of(id);
+        Ok(())
+    }
+    // moves the cursors of other windows on the current buffer along with
+    // the edits made through this one
+    pub fn sync_windows(&mut self) {
+        let edits = self.buffer.take_edits();
+        let last = self.buffer.line_count().saturating_sub(1);
+        let current = self.buffers.current;
+        for window in self.windows.others.iter_mut() {
+            if window.buffer != current {
+                continue;
+            }
+            let cursor = &mut window.cursor;
+            (cursor.y, cursor.x) = edits.iter().fold((cursor.y, cursor.x), |p, e| e.shift(p));
+            cursor.y = cursor.y.min(last);
+            cursor.x = cursor.x.min(self.buffer.lines[cursor.y].grapheme_len());
+        }
+    }
+    // the current window's view and cursor, moved out of the editor
+    fn stash_window(&mut self) -> Window {
+        Window {
+            id: self.windows.current,
+            buffer: self.buffers.current,
+            view: std::mem::take(&mut self.view),
+            cursor: self.cursor,
+        }
+    }
+    // makes window the current one, with its buffer
+    fn unstash_window(&mut self, window: Window) -> Result<()> {
+        self.windows.current = window.id;
+        self.enter_buffer(window.buffer)?;
+        self.view = window.view;
+        self.cursor = window.cursor;
+        self.clamp_cursor();
+        self.update_view();
+        Ok(())
+    }
+    pub fn enter_window(&mut self, id: usize) -> Result<()> {
+        if id == self.windows.current {
+            return Ok(());
+        }
+        self.sync_windows();
+        let target = self
+            .windows
+            .take(id)
+            .ok_or_else(|| command::invalid(format!("window {} does not exist", id)))?;
+        let current = self.stash_window();
+        self.windows.others.push(current);
+        self.unstash_window(target)
+    }
+    // :split and :vsplit; the new window shows the same buffer at the same
+    // place and comes above or left of the old one
+    pub fn split_window(&mut self, stack: Stack) -> Result<()> {
+        if !self.windows.can_split(stack) {
+            return Err(command::invalid("not enough room".to_owned()));
+        }
+        self.sync_windows();
+        let id = self.windows.new_id();
+        let old = self.windows.current;
+        self.windows.others.push(Window {
+            id: old,
+            buffer: self.buffers.current,
+            view: self.view.clone(),
+            cursor: self.cursor,
+        });
+        self.windows.layout.split(old, id, stack);
+        self.windows.current = id;
+        Ok(())
+    }
+    // the window before it in the layout, or the next one for the first,
+    // takes over; the buffer stays in the list, so nothing is lost
+    pub fn close_window(&mut self) -> Result<()> {
+        let closing = self.windows.current;
+        let first = self.windows.layout.ids().first() == Some(&closing);
+        let next = self.windows.cycle(if first { 1 } else { -1 });
+        if next == closing {
+            return Err(command::invalid("cannot close last window".to_owned()));
+        }
+        self.sync_windows();
+        let target = self
+            .windows
+            .take(next)
+            .ok_or_else(|| command::invalid("no window to go to".to_owned()))?;
+        self.windows.layout.remove(closing);
+        self.unstash_window(target)
+    }
+    pub fn only_window(&mut self) {
+        self.windows.others.clear();
+        self.windows.layout = Layout::Window(self.windows.current);
+    }
+    fn close_windows_of(&mut self, buffer: usize) {
+        let closing = self
+            .windows
+            .others
+            .iter()
+            .filter(|w| w.buffer == buffer)
+            .map(|w| w.id)
+            .collect::<Vec<_>>();
+        for id in closing {
+            self.windows.take(id);
+            self.windows.layout.remove(id);
+        }
+    }
+    // :ls, with vim's flags: % current, # alternate, h hidden, + modified
+    pub fn list_buffers(&mut self) {
+        let current = self.buffers.current;
+        let alternate = self.buffers.alternate;
+        let mut lines = Vec::new();
+        for id in self.buffers.ids() {
+            let (name, modified, line) = match self.buffers.hidden().find(|h| h.id == id) {
+                Some(h) => (
+                    h.file.as_ref().map(|f| f.display().to_string()),
+                    h.is_modified(),
+                    h.cursor.y,
+                ),
+                None => (
+                    self.current_file.as_ref().map(|f| f.display().to_string()),
+                    self.is_modified(),
+                    self.cursor.y,
+                ),
+            };
+            let flag = match id {
+                _ if id == current => "%a",
+                _ if Some(id) == alternate => "#h",
+                _ => " h",
+            };
+            lines.push(format!(
+                "{:>3} {} {} \"{}\" line {}",
+                id,
+                flag,
+                if modified { '+' } else { ' ' },
+                name.unwrap_or_else(|| "[No Name]".to_owned()),
+                line + 1
+            ));
+        }
+        self.messages = lines;
+    }
+    // the first hidden buffer with unsaved changes is made current so :q
+    // shows what is in the way
+    fn check_hidden_changes(&mut self) -> Result<()> {
+        let Some(id) = self
+            .buffers
+            .hidden()
+            .find(|h| h.is_modified())
+            .map(|h| h.id)
+        else {
+            return Ok(());
+        };
+        self.enter_buffer(id)?;
+        Err(command::invalid(format!(
+            "no write since last change for buffer \"{}\" (add ! to override)",
+            self.buffer_name()
+        )))
+    }
+    // brings the highlighting of lines above end up to date with the edits
+    // made since the last call
+    pub fn refresh_syntax(&mut self, end: usize) {
+        if let Some(y) = self.buffer.take_changed() {
+            self.syntax.invalidate(y);
+        }
+        self.syntax.update(&self.buffer, end);
+    }
+    fn load_contents(&mut self, bytes: Vec<u8>) {
+        if hex::is_binary(&bytes) {
+            self.buffer = Buffer::default();
+            options::inherit(self, Scope::Buffer);
+            self.hex = Some(HexEditor::new(bytes));
+        } else {
+            self.hex = None;
+            self.buffer.load_bytes(&bytes);
+        }
+    }
+    fn contents_bytes(&self) -> Vec<u8> {
+        match &self.hex {
+            Some(hex) => hex.bytes.clone(),
+            None => self
+                .buffer
+                .to_bytes()
+                .unwrap_or_else(|_| self.buffer.buffer_to_string().into_bytes()),
+        }
+    }
+    pub fn is_modified(&self) -> bool {
+        match &self.hex {
+            Some(hex) => hex.modified,
+            None => self.buffer.modified,
+        }
+    }
+    fn set_modified(&mut self, modified: bool) {
+        match &mut self.hex {
+            Some(hex) => hex.modified = modified,
+            None => self.buffer.modified = modified,
+        }
+    }
+    fn change_tick(&self) -> usize {
+        self.buffer.changes + self.hex.as_ref().map_or(0, |hex| hex.changes)
+    }
+    fn check_swap(&mut self, at: &Path) {
+        self.found_swap = None;
+        match swap::find(at) {
+            Some((path, info)) if info.process_alive() => {
+                self.set_status(format!(
+                    "swap file {} is in use by another ve (pid {})",
+                    path.display(),
+                    info.pid
+                ));
+                return;
+            }
+            Some((path, info)) if info.body.is_some() => {
+                self.set_status(format!(
+                    "found swap file {} with unsaved changes: :recover to restore, :recover! to delete",
+                    path.display()
+                ));
+                self.found_swap = Some(path);
+                return;
+            }
+            Some((path, _)) => {
+                let _ = fs::remove_file(path);
+            }
+            None => {}
+        }
+        self.swap = Swap::create(at).ok();
+        self.swap_tick = self.change_tick();
+    }
+    pub fn update_swap(&mut self) -> Result<()> {
+        if self.change_tick().saturating_sub(self.swap_tick) >= swap::UPDATE_COUNT {
+            self.sync_swap()?;
+        }
+        Ok(())
+    }
+    pub fn sync_swap(&mut self) -> Result<()> {
+        if let Some(swap) = &self.swap {
+            let body = self.is_modified().then(|| self.contents_bytes());
+            swap.write(body.as_deref())?;
+        }
+        self.swap_tick = self.change_tick();
         Ok(())
     }
+    pub fn recover(&mut self, discard: bool) -> Result<()> {
+        let found = self.found_swap.take();
+        let path = match (found, self.current_file.as_deref()) {
+            (Some(path), _) => path,
+            (None, Some(file)) => swap::find(file)
+                .map(|(path, _)| path)
+                .ok_or_else(|| command::invalid("no swap file found".to_owned()))?,
+            (None, None) => return Err(command::invalid("no file name".to_owned())),
+        };
+        if discard {
+            fs::remove_file(&path)?;
+            if let Some(file) = self.current_file.clone() {
+                self.check_swap(&file);
+            }
+            self.set_status(format!("deleted swap file {}", path.display()));
+            return Ok(());
+        }
+        let info = SwapInfo::parse(&fs::read(&path)?)?;
+        let Some(body) = info.body else {
+            return Err(command::invalid(format!(
+                "{} has no unsaved changes",
+                path.display()
+            )));
+        };
+        let before = self.buffer.buffer_to_string();
+        self.load_contents(body);
+        self.set_modified(true);
+        self.cursor = Cursor::default();
+        self.view.reset_scroll();
+        if self.hex.is_none() {
+            let after = self.buffer.buffer_to_string();
+            let old = before.split('\n').collect::<Vec<_>>();
+            let new = after.split('\n').collect::<Vec<_>>();
+            self.messages = vec![format!("recovered from {}:", path.display())];
+            self.messages.extend(diff::unified(&old, &new));
+        }
+        self.set_status(format!(
+            "recovered from {}; :w to keep the changes",
+            path.display()
+        ));
+        self.swap = Some(Swap::adopt(path));
+        self.sync_swap()
+    }
+    // every modified buffer goes to its swap file, or to a recovery file
+    // when it has none
+    // refreshes the copy of the unsaved buffers the panic hook writes out;
+    // hidden buffers do not change, so it is only redone when the current
+    // buffer or the list of buffers did
+    pub fn snapshot(&mut self) {
+        let hidden_key = (self.buffers.current, self.buffers.ids());
+        if self.hidden_key.as_ref() != Some(&hidden_key) {
+            swap::set_hidden_snapshot(self.hidden_unsaved());
+            self.hidden_key = Some(hidden_key);
+        }
+        let key = (self.buffers.current, self.change_tick(), self.is_modified());
+        if self.snapshot_key == Some(key) {
+            return;
+        }
+        self.snapshot_key = Some(key);
+        let current = self.is_modified().then(|| Dump {
+            swap: self.swap.as_ref().map(|s| s.path().to_path_buf()),
+            name: dump_name(self.current_file.as_ref()),
+            contents: self.contents_bytes(),
+        });
+        swap::set_snapshot(current);
+    }
+    fn hidden_unsaved(&self) -> Vec<Dump> {
+        let mut dumps = Vec::new();
+        for hidden in self.buffers.hidden().filter(|h| h.is_modified()) {
+            dumps.push(Dump {
+                swap: hidden.swap.as_ref().map(|s| s.path().to_path_buf()),
+                name: dump_name(hidden.file.as_ref()),
+                contents: hidden.contents_bytes(),
+            });
+        }
+        dumps
+    }
+    pub fn close(&mut self) {
+        self.close_swaps(false);
+    }
+    // keep_changed leaves the swap files of changed buffers in place with
+    // their unsaved text, for a session that ends on an error, not a quit
+    fn close_swaps(&mut self, keep_changed: bool) {
+        if keep_changed && self.is_modified() {
+            let _ = self.sync_swap();
+            self.swap = None;
+        }
+        if let Some(swap) = self.swap.take() {
+            swap.remove();
+        }
+        for hidden in self.buffers.hidden_mut() {
+            let Some(swap) = hidden.swap.take() else {
+                continue;
+            };
+            if keep_changed && hidden.is_modified() {
+                let _ = swap.write(Some(&hidden.contents_bytes()));
+            } else {
+                swap.remove();
+            }
+        }
+    }
+    fn file_info(&self) -> String {
+        let mut info = format!("{}L", self.buffer.line_count());
+        if self.buffer.encoding != Encoding::Utf8 || self.buffer.bom {
+            let bom = if self.buffer.bom { ",bom" } else { "" };
+            info.push_str(&format!(" [{}{}]", self.buffer.encoding.name(), bom));
+        }
+        if self.buffer.line_ending != LineEnding::Lf {
+            info.push_str(&format!(" [{}]", self.buffer.line_ending.name()));
+        }
+        if !self.buffer.final_newline {
+            info.push_str(" [noeol]");
+        }
+        if self.buffer.readonly {
+            info.push_str(" [readonly]");
+        }
+        if self.buffer.invalid > 0 && self.hex.is_none() {
+            info.push_str(&format!(" [{} bad utf-8 sequences]", self.buffer.invalid));
+        }
+        info
+    }
     pub fn write_file(&self, path: &Path) -> Result<()> {
-        fs::write(path, self.buffer.buffer_to_string())?;
+        match &self.hex {
+            Some(hex) => file::write_atomic(path, &hex.bytes, self.backup),
+            None => file::write_atomic(path, &self.buffer.to_bytes()?, self.backup),
+        }
+    }
+    pub fn save(&mut self, path: Option<PathBuf>, force: bool) -> Result<()> {
+        let path = match path.or_else(|| self.current_file.clone()) {
+            Some(path) => path,
+            None => return Err(command::invalid("no file name".to_owned())),
+        };
+        if !force && self.buffer.readonly {
+            return Err(command::invalid(
+                "'readonly' is set (add ! to override)".to_owned(),
+            ));
+        }
+        if !force && self.current_file.as_ref() == Some(&path) && self.external_change() {
+            self.set_status(String::new());
+            self.prompt = Some(Prompt::Overwrite(path));
+            return Ok(());
+        }
+        self.write_out(path)
+    }
+    fn write_out(&mut self, path: PathBuf) -> Result<()> {
+        self.write_file(&path)?;
+        if self.current_file.is_none() {
+            self.current_file = Some(path.clone());
+            self.check_swap(&path);
+        }
+        if self.current_file.as_ref() == Some(&path) {
+            self.disk = FileStamp::read(&path).ok();
+            self.set_modified(false);
+            self.sync_swap()?;
+        }
+        let size = match &self.hex {
+            Some(hex) => format!("{}B", hex.bytes.len()),
+            None => format!("{}L", self.buffer.line_count()),
+        };
+        self.set_status(format!("\"{}\" {} written", path.display(), size));
         Ok(())
     }
+    pub fn external_change(&self) -> bool {
+        match (&self.current_file, &self.disk) {
+            (Some(path), Some(stamp)) => stamp.changed(path),
+            (Some(path), None) => path.exists(),
+            (None, _) => false,
+        }
+    }
+    pub fn check_external_change(&mut self) -> Result<()> {
+        if self.prompt.is_some() || !self.external_change() {
+            return Ok(());
+        }
+        if self.autoread && !self.is_modified() {
+            self.reload()?;
+            self.set_status("file changed on disk; reloaded".to_owned());
+        } else {
+            self.prompt = Some(Prompt::Reload);
+        }
+        Ok(())
+    }
+    pub fn reload(&mut self) -> Result<()> {
+        let Some(path) = self.current_file.clone() else {
+            return Ok(());
+        };
+        let bytes = fs::read(&path)?;
+        self.disk = fs::metadata(&path).ok().map(|m| FileStamp::new(&m, &bytes));
+        self.load_contents(bytes);
+        self.clamp_cursor();
+        self.sync_swap()
+    }
+    pub fn answer_prompt(&mut self, yes: bool) -> Result<()> {
+        match (self.prompt.take(), yes) {
+            (Some(Prompt::Overwrite(path)), true) => self.write_out(path)?,
+            (Some(Prompt::Overwrite(_)), false) => self.set_status("not written".to_owned()),
+            (Some(Prompt::Reload), true) => {
+                self.reload()?;
+                self.set_status("reloaded".to_owned());
+            }
+            (Some(Prompt::Reload), false) => {
+                // keep our version and stop asking until the file changes again
+                self.disk = self
+                    .current_file
+                    .as_deref()
+                    .and_then(|p| FileStamp::read(p).ok());
+            }
+            (None, _) => {}
+        }
+        Ok(())
+    }
+    fn clamp_cursor(&mut self) {
+        let last = self.buffer.line_count().saturating_sub(1);
+        self.cursor.y = self.cursor.y.min(last);
+        self.cursor.x = self
+            .cursor
+            .x
+            .min(self.buffer.lines[self.cursor.y].grapheme_len());
+        if let Some(hex) = &mut self.hex {
+            hex.cursor.offset = hex.cursor.offset.min(hex.bytes.len());
+        }
+    }
+    pub fn execute_command(&mut self, input: &str) -> Result<()> {
+        match Command::parse(input)? {
+            Command::Write { path, force } => self.save(path, force)?,
+            Command::Quit { .. } | Command::Close { .. } if !self.windows.others.is_empty() => {
+                self.close_window()?;
+            }
+            Command::Close { .. } => {
+                return Err(command::invalid("cannot close last window".to_owned()));
+            }
+            Command::Quit { force } | Command::QuitAll { force } => {
+                if !force && self.is_modified() {
+                    return Err(command::invalid(
+                        "no write since last change (add ! to override)".to_owned(),
+                    ));
+                }
+                if !force {
+                    self.check_hidden_changes()?;
+                }
+                self.quit = true;
+            }
+            Command::Edit { path, force } => self.edit(path, force)?,
+            Command::Split { stack, path } => {
+                self.split_window(stack)?;
+                if path.is_some() {
+                    self.edit(path, false)?;
+                }
+            }
+            Command::Only => self.only_window(),
+            Command::Next => self.goto_arg(self.arglist.index + 1)?,
+            Command::Previous => match self.arglist.index.checked_sub(1) {
+                Some(i) => self.goto_arg(i)?,
+                None => return Err(command::invalid("cannot go before first file".to_owned())),
+            },
+            Command::Args => self.set_status(self.arglist.summary()),
+            Command::Bnext => self.switch_buffer(self.buffers.cycle(1))?,
+            Command::Bprevious => self.switch_buffer(self.buffers.cycle(-1))?,
+            Command::Buffer(name) => self.switch_buffer(self.find_buffer(&name)?)?,
+            Command::Buffers => self.list_buffers(),
+            Command::Bdelete { target, force } => self.delete_buffer(target.as_deref(), force)?,
+            Command::Recover { discard } => self.recover(discard)?,
+            Command::WriteQuit if !self.windows.others.is_empty() => {
+                self.save(None, false)?;
+                if self.prompt.is_none() {
+                    self.close_window()?;
+                }
+            }
+            Command::WriteQuit => {
+                self.save(None, false)?;
+                if self.prompt.is_none() {
+                    self.check_hidden_changes()?;
+                }
+                self.quit = self.prompt.is_none();
+            }
+            Command::Checktime => self.check_external_change()?,
+            Command::Hex => self.toggle_hex()?,
+            Command::Stats => self.set_status(self.render_stats.summary()),
+            Command::Colorscheme(None) => self.set_status(self.theme.name.clone()),
+            Command::Colorscheme(Some(name)) => {
+                self.theme = Theme::load(&name)?.degrade(ColorDepth::detect());
+            }
+            Command::Set(args) => {
+                for arg in args {
+                    self.set_option(&arg)?;
+                }
+            }
+            Command::Map {
+                modes,
+                lhs: Some(lhs),
+                rhs: Some(rhs),
+                noremap,
+            } => {
+                for &mode in modes {
+                    self.keymap.map(mode, &lhs, &rhs, noremap)?;
+                }
+            }
+            Command::Map { modes, lhs, .. } => {
+                let lines = self.keymap.list(modes, lhs.as_deref().unwrap_or(""));
+                if lines.is_empty() {
+                    self.set_status("no mapping found".to_owned());
+                } else {
+                    self.messages = lines;
+                }
+            }
+            Command::Unmap { modes, lhs } => {
+                let results = modes
+                    .iter()
+                    .map(|&mode| self.keymap.unmap(mode, &lhs))
+                    .collect::<Vec<_>>();
+                if results.iter().all(|r| r.is_err()) {
+                    return Err(command::invalid(format!("no such mapping: {}", lhs)));
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn toggle_hex(&mut self) -> Result<()> {
+        match self.hex.take() {
+            Some(hex) => {
+                self.buffer.load_bytes(&hex.bytes);
+                self.buffer.modified = hex.modified;
+                self.cursor = Cursor::default();
+                self.view.reset_scroll();
+            }
+            None => {
+                let mut hex = HexEditor::new(self.buffer.to_bytes()?);
+                hex.modified = self.buffer.modified;
+                self.hex = Some(hex);
+            }
+        }
+        Ok(())
+    }
+    pub fn set_option(&mut self, arg: &str) -> Result<()> {
+        options::apply(self, arg)
+    }
+    // runs every line of a config file as an ex command; blank lines and
+    // lines starting with " or # are skipped. returns one error per bad line
+    pub fn source(&mut self, contents: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') || line.starts_with('#') {
+                continue;
+            }
+            let command = line.strip_prefix(':').unwrap_or(line);
+            if let Err(e) = self.execute_command(command) {
+                errors.push(format!("line {}: {}", i + 1, e));
+            }
+        }
+        errors
+    }
+    // $XDG_CONFIG_HOME/ve/config; its errors go to the message pager so they
+    // are seen before editing starts
+    pub fn load_config(&mut self) {
+        let Some(path) = file::config_dir().map(|d| d.join("config")) else {
+            return;
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return,
+            Err(e) => {
+                self.messages = vec![format!("{}: {}", path.display(), e)];
+                return;
+            }
+        };
+        let errors = self.source(&contents);
+        if !errors.is_empty() {
+            self.messages = vec![format!("errors in {}:", path.display())];
+            self.messages.extend(errors);
+        }
+    }
+    pub fn set_status(&mut self, msg: String) {
+        self.status = msg;
+    }
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+    pub fn quit(&mut self) -> Result<()> {
+        self.execute_command("qa")
+    }
     pub fn set_mode(&mut self, mode: Mode) {
         self.mode = mode;
     }
     pub fn get_mode(&self) -> Mode {
         self.mode
     }
+    pub fn viewport(&self) -> Size {
+        self.viewport
+    }
+    // the size of the text area, not counting the status line
+    pub fn resize(&mut self, size: Size) {
+        self.viewport = size;
+        self.update_view();
+    }
     pub fn update_view(&mut self) {
-        let (cols, rows) = ratatui::termion::terminal_size().unwrap_or((80, 24));
-        let (_max_cols, max_rows) = (cols as usize, rows as usize);
-        if self.buffer.line_count() == 0 {
-            self.view.offset_x = 0;
-            self.view.offset_y = 0;
+        let max_rows = self.viewport.height.max(1) as usize;
+        if let Some(hex) = &mut self.hex {
+            hex.scroll(max_rows);
             return;
         }
-        let (new_offset_x, new_offset_y) = self.cursor.maybe_scroll(&self.view);
-        let line = &self.buffer.lines[new_offset_y];
-        self.view.offset_x = new_offset_x.min(line.grapheme_len());
-        let max_offset_y = self.buffer.line_count().saturating_sub(max_rows);
-        self.view.offset_y = new_offset_y.min(max_offset_y);
+        self.cursor
+            .follow(&mut self.view, &self.buffer, self.viewport);
     }
-    pub fn update_cursor(&self, stdout: &mut std::io::Stdout) -> Result<()> {
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.render_stateful_widget(EditorView, frame.area(), self);
+        if let Some(position) = self.screen_cursor {
+            frame.set_cursor_position(position);
+        }
+    }
+    // gj and gk: up or down one screen row, which is a whole line without wrap
+    pub fn move_visual(&mut self, down: bool) {
+        let width = self.viewport.width.max(1) as usize;
         self.cursor
-            .render_cursor(self.view.offset_x, self.view.offset_y, stdout)
+            .move_visual(&self.view, &self.buffer, width, down);
     }
     pub fn handle_cursor(&mut self, key: Key) -> Result<()> {
         match key {
@@ -94,34 +1092,108 @@ impl Editor {
         Ok(())
     }
     pub fn delete_under_cursor(&mut self) {
-        let line_len = self.buffer.lines[self.cursor.y].grapheme_len();
-        if self.cursor.x < line_len {
-            let line = &mut self.buffer.lines[self.cursor.y];
-            line.remove(self.cursor.x);
-        } else if self.cursor.y + 1 < self.buffer.line_count() {
-            let next = self.buffer.lines.remove(self.cursor.y + 1);
-            self.buffer.lines[self.cursor.y].push_str(&next.raw);
-        }
-    }
-    pub fn run(&mut self, stdout: &mut std::io::Stdout) -> Result<()> {
-        write!(
-            stdout,
-            "{}{}",
-            ratatui::termion::clear::All,
-            ratatui::termion::cursor::Goto(1, 1)
-        )
-        .unwrap();
-        stdout.flush().unwrap();
-        self.view.render(stdout, &self.buffer)?;
-        self.update_cursor(stdout)?;
-        let stdin = stdin();
-        for k in stdin.keys() {
-            let key = k?;
-            let mut kh = KeyHandler::new(self);
-            kh.process_key(key, stdout)?;
-            self.view.render(stdout, &self.buffer)?;
-            self.update_cursor(stdout)?;
-            stdout.flush().unwrap();
+        self.buffer.delete_forward(&Location::from(self.cursor));
+    }
+    fn schedule_idle(&mut self) {
+        if self.swap.is_some() && self.change_tick() != self.swap_tick {
+            self.events.set_timer(Timer::Swap, swap::UPDATE_TIME);
+        } else {
+            self.events.cancel_timer(Timer::Swap);
+        }
+        if self.autosave > 0 && self.is_modified() {
+            let after = Duration::from_secs(self.autosave as u64);
+            self.events.set_timer(Timer::Autosave, after);
+        } else {
+            self.events.cancel_timer(Timer::Autosave);
+        }
+    }
+    pub fn on_timer(&mut self, timer: Timer) -> Result<()> {
+        match timer {
+            Timer::Swap => self.sync_swap(),
+            Timer::Autosave => self.autosave(),
+            Timer::Mapping => self.run_typeahead(true),
+        }
+    }
+    pub fn autosave(&mut self) -> Result<()> {
+        if self.autosave > 0
+            && self.is_modified()
+            && self.current_file.is_some()
+            && self.prompt.is_none()
+        {
+            self.save(None, false)?;
+        }
+        Ok(())
+    }
+    pub fn handle_key(&mut self, key: Key) -> Result<()> {
+        self.typeahead.push(key);
+        self.run_typeahead(false)
+    }
+    // timed_out stops waiting for a longer mapping and runs what is there
+    fn run_typeahead(&mut self, timed_out: bool) -> Result<()> {
+        self.events.cancel_timer(Timer::Mapping);
+        let result = KeyHandler::new(self).process_typeahead(timed_out);
+        if result.is_err() {
+            self.typeahead.clear();
+        }
+        result?;
+        if let Err(e) = self.update_swap() {
+            self.set_status(format!("cannot write swap file: {}", e));
+        }
+        self.schedule_idle();
+        Ok(())
+    }
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+    pub fn handle_event(&mut self, event: Event) -> Result<()> {
+        let result = match event {
+            Event::Key(key) => return self.handle_key(key),
+            Event::FocusIn => self.check_external_change(),
+            Event::FocusOut => self.autosave(),
+            // the next draw picks up the new size
+            Event::Resize => Ok(()),
+            Event::Timer(timer) => self.on_timer(timer),
+            Event::Message(apply) => apply(self),
+            Event::Error(e) => return Err(e),
+            Event::InputClosed => {
+                self.quit = true;
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.set_status(e.to_string());
+        }
+        Ok(())
+    }
+    // the swap files go on every way out; an error ends the session like a
+    // crash would, so the ones with unsaved text stay for :recover
+    pub fn run<B: Backend<Error = io::Error>>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        let result = self.event_loop(terminal);
+        self.close_swaps(result.is_err());
+        result
+    }
+    fn event_loop<B: Backend<Error = io::Error>>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        terminal.clear()?;
+        terminal.draw(|frame| self.draw(frame))?;
+        self.events.spawn_input(stdin());
+        if let Err(e) = self.events.watch_resize() {
+            self.set_status(format!("cannot watch for resizes: {}", e));
+        }
+        while let Some(event) = self.events.wait() {
+            let key = matches!(event, Event::Key(_));
+            self.handle_event(event)?;
+            self.snapshot();
+            if self.quit {
+                break;
+            }
+            let before = self.output.get();
+            terminal.draw(|frame| self.draw(frame))?;
+            if key {
+                self.render_stats.record(self.output.get() - before);
+            }
         }
         Ok(())
     }
@@ -130,7 +1202,8 @@ impl Editor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::buffer::{Line, Location};
+    use crate::args::{self, Action};
+    use crate::buffer::Line;
     use std::fs;
     use std::path::PathBuf;
 
@@ -165,7 +1238,7 @@ mod tests {
         }
         editor.write_file(&path).expect("write_file");
         let content = fs::read_to_string(&path).unwrap();
-        assert_eq!(content, "hello");
+        assert_eq!(content, "hello\n");
     }
 
     #[test]
@@ -198,6 +1271,480 @@ mod tests {
         assert_eq!(editor.buffer.lines[0].as_str(), "abcdef");
     }
 
+    #[test]
+    fn test_new_file_is_written_with_final_newline() {
+        let path = temp_file_path("test_new_eol_editor.txt");
+        let _ = fs::remove_file(&path);
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open_file");
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, 'a');
+        editor.write_file(&path).expect("write_file");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_preserves_crlf_and_final_newline() {
+        let path = temp_file_path("test_crlf_editor.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open_crlf");
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '>');
+        editor.write_file(&path).expect("write_crlf");
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">one\r\ntwo\r\n");
+        editor.execute_command("set ff=unix noeol").expect("set");
+        editor.write_file(&path).expect("write_unix");
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">one\ntwo");
+    }
+
+    #[test]
+    fn test_fileencoding_reencodes_on_write() {
+        let path = temp_file_path("test_fenc_editor.txt");
+        fs::write(&path, b"caf\xE9\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open_latin1");
+        assert_eq!(editor.buffer.lines[0].as_str(), "café");
+        editor.execute_command("set fenc=utf-8").expect("set");
+        editor.write_file(&path).expect("write_utf8");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "café\n");
+    }
+
+    #[test]
+    fn test_binary_file_round_trips_through_hex_mode() {
+        let path = temp_file_path("test_binary_editor.bin");
+        let bytes = b"\x7fELF\0\x01\xff\r\n".to_vec();
+        fs::write(&path, &bytes).unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open_binary");
+        let hex = editor.hex.as_mut().expect("binary file opens in hex mode");
+        hex.set_nibble(0x4);
+        hex.set_nibble(0x5);
+        editor.write_file(&path).expect("write_binary");
+        assert_eq!(fs::read(&path).unwrap(), b"\x45ELF\0\x01\xff\r\n");
+    }
+
+    #[test]
+    fn test_hex_command_toggles_text_buffer() {
+        let mut editor = Editor::default();
+        editor.buffer.load_str("hi\n");
+        editor.execute_command("hex").expect("hex");
+        assert_eq!(editor.hex.as_ref().unwrap().bytes, b"hi\n");
+        editor.hex.as_mut().unwrap().set_nibble(0x4);
+        editor.execute_command("hex").expect("unhex");
+        assert!(editor.hex.is_none());
+        assert_eq!(editor.buffer.line_at(0), "Hi");
+    }
+
+    #[test]
+    fn test_recover_restores_swap_contents() {
+        let dir = std::env::temp_dir().join(format!("ve_recover_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("draft.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut crashed = Editor::default();
+        crashed.open_file(&path).expect("open");
+        crashed.buffer.insert_char(&Location { x: 3, y: 1 }, '!');
+        crashed.sync_swap().expect("swap");
+        // the crashed editor never closes, so its swap file stays behind
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("reopen");
+        assert!(editor.status().contains(":recover"));
+        editor.execute_command("recover").expect("recover");
+        assert_eq!(editor.buffer.buffer_to_string(), "one\ntwo!\n");
+        assert!(editor.is_modified());
+        assert!(editor.messages.contains(&"+ two!".to_owned()));
+        assert!(editor.execute_command("q").is_err());
+        editor.execute_command("w").expect("write");
+        editor.close();
+        assert!(swap::find(&path).is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo!\n");
+    }
+
+    #[test]
+    fn test_swap_files_go_when_the_session_ends_on_an_error() {
+        let path = temp_file_path("test_run_error.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).unwrap();
+        assert!(swap::find(&path).is_some());
+        editor.close_swaps(true);
+        assert!(swap::find(&path).is_none());
+        // unsaved text is kept for :recover
+        let mut editor = Editor::default();
+        editor.open_file(&path).unwrap();
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '>');
+        editor.buffer.modified = true;
+        editor.close_swaps(true);
+        let (swap, info) = swap::find(&path).unwrap();
+        assert_eq!(info.body.as_deref(), Some(&b">one\n"[..]));
+        fs::remove_file(swap).unwrap();
+    }
+
+    #[test]
+    fn test_panic_snapshot_writes_unnamed_buffer() {
+        let mut editor = Editor::default();
+        editor.snapshot();
+        assert!(swap::dump_snapshot().is_empty());
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, 'z');
+        editor.snapshot();
+        let saved = swap::dump_snapshot();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(fs::read_to_string(&saved[0]).unwrap(), "z\n");
+        fs::remove_file(&saved[0]).unwrap();
+        // once hidden, the buffer is dumped along with the one being typed in
+        editor.add_buffer();
+        editor.current_file = Some(temp_file_path("test_snapshot_second.txt"));
+        for x in 0..2 {
+            editor.buffer.insert_char(&Location { x, y: 0 }, 'y');
+            editor.snapshot();
+        }
+        let mut saved = swap::dump_snapshot();
+        saved.sort();
+        let contents = saved.iter().map(|p| fs::read_to_string(p).unwrap());
+        assert_eq!(contents.collect::<Vec<_>>(), ["yy\n", "z\n"]);
+        for path in saved {
+            fs::remove_file(path).unwrap();
+        }
+        swap::set_snapshot(None);
+        swap::set_hidden_snapshot(Vec::new());
+    }
+
+    #[test]
+    fn test_write_asks_before_overwriting_external_change() {
+        let path = temp_file_path("test_external_editor.txt");
+        fs::write(&path, "mine\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open");
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '>');
+        fs::write(&path, "theirs, longer\n").unwrap();
+        editor.save(None, false).expect("save");
+        assert!(matches!(editor.prompt, Some(Prompt::Overwrite(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs, longer\n");
+        editor.answer_prompt(true).expect("overwrite");
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">mine\n");
+        assert!(!editor.external_change());
+        editor.close();
+    }
+
+    #[test]
+    fn test_autoread_reloads_unmodified_buffer() {
+        let path = temp_file_path("test_autoread_editor.txt");
+        fs::write(&path, "old\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open");
+        editor.execute_command("set autoread").expect("set");
+        fs::write(&path, "new contents\n").unwrap();
+        editor.check_external_change().expect("check");
+        assert!(editor.prompt.is_none());
+        assert_eq!(editor.buffer.line_at(0), "new contents");
+        // a modified buffer is never replaced without asking
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '!');
+        fs::write(&path, "newer contents\n").unwrap();
+        editor.check_external_change().expect("check");
+        assert_eq!(editor.prompt, Some(Prompt::Reload));
+        editor.close();
+    }
+
+    #[test]
+    fn test_autosave_after_idle() {
+        let path = temp_file_path("test_autosave_editor.txt");
+        fs::write(&path, "draft\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).expect("open");
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '#');
+        editor.schedule_idle();
+        assert!(!editor.events.timer_pending(Timer::Autosave));
+        editor.execute_command("set autosave=1").expect("set");
+        editor.schedule_idle();
+        assert!(editor.events.timer_pending(Timer::Autosave));
+        editor.on_timer(Timer::Autosave).expect("autosave");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#draft\n");
+        assert!(!editor.is_modified());
+        editor.close();
+    }
+
+    #[test]
+    fn test_redraw_only_sends_changed_cells() {
+        use crate::terminal::CountingWriter;
+        use ratatui::backend::TermionBackend;
+        use ratatui::layout::Rect;
+        use ratatui::{TerminalOptions, Viewport};
+        let mut editor = Editor::default();
+        editor
+            .buffer
+            .load_str(&"some text on every line\n".repeat(30));
+        let writer = CountingWriter::new(Vec::new(), editor.output.clone());
+        let mut terminal = Terminal::with_options(
+            TermionBackend::new(writer),
+            TerminalOptions {
+                viewport: Viewport::Fixed(Rect::new(0, 0, 80, 24)),
+            },
+        )
+        .unwrap();
+        terminal.draw(|frame| editor.draw(frame)).unwrap();
+        let full = editor.output.get();
+        editor.handle_event(Event::Key(Key::Char('i'))).unwrap();
+        editor.handle_event(Event::Key(Key::Char('x'))).unwrap();
+        terminal.draw(|frame| editor.draw(frame)).unwrap();
+        let typed = editor.output.get() - full;
+        assert!(full > 23 * 23, "first frame draws every line: {}", full);
+        assert!(typed < 64, "one keystroke rewrote {} bytes", typed);
+        editor.close();
+    }
+
+    #[test]
+    fn test_source_reports_line_numbers() {
+        let mut editor = Editor::default();
+        let errors = editor.source("\" comment\nset ts=4 nu\n\n:set bogus\nset so=x\n");
+        assert_eq!(
+            errors,
+            [
+                "line 4: unknown option: bogus",
+                "line 5: invalid scrolloff: x"
+            ]
+        );
+        assert_eq!(editor.buffer.tabstop, 4);
+        assert!(editor.view.number);
+        editor.set_option("ts&").unwrap();
+        assert_eq!(editor.buffer.tabstop, 8);
+    }
+
+    #[test]
+    fn test_ambiguous_mapping_waits_for_the_timeout() {
+        let mut editor = Editor::default();
+        editor.execute_command("imap jk <Esc>").unwrap();
+        editor.handle_key(Key::Char('i')).unwrap();
+        editor.handle_key(Key::Char('j')).unwrap();
+        assert_eq!(editor.buffer.lines[0].as_str(), "");
+        assert!(editor.events.timer_pending(Timer::Mapping));
+        editor.on_timer(Timer::Mapping).unwrap();
+        assert_eq!(editor.buffer.lines[0].as_str(), "j");
+        editor.handle_key(Key::Char('j')).unwrap();
+        editor.handle_key(Key::Char('k')).unwrap();
+        assert_eq!(editor.buffer.lines[0].as_str(), "j");
+        assert_eq!(editor.get_mode(), Mode::Normal);
+        assert!(!editor.events.timer_pending(Timer::Mapping));
+    }
+
+    #[test]
+    fn test_recursive_mapping_stops() {
+        let mut editor = Editor::default();
+        editor.execute_command("nmap a b").unwrap();
+        editor.execute_command("nmap b a").unwrap();
+        editor.handle_key(Key::Char('a')).unwrap();
+        assert_eq!(editor.status(), "recursive mapping");
+        assert!(editor.typeahead.is_empty());
+        assert!(editor.execute_command("unmap c").is_err());
+    }
+
+    #[test]
+    fn test_buffers_keep_their_own_text_and_cursor() {
+        let a = temp_file_path("test_buffers_a.txt");
+        let b = temp_file_path("test_buffers_b.txt");
+        fs::write(&a, "alpha\n").unwrap();
+        fs::write(&b, "beta\nbeta two\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&a).unwrap();
+        editor.cursor.x = 3;
+        editor
+            .execute_command(&format!("e {}", b.display()))
+            .unwrap();
+        assert_eq!(editor.buffers.current, 2);
+        assert_eq!(editor.buffer.line_at(0), "beta");
+        editor.cursor.y = 1;
+        editor.handle_key(Key::Char('x')).unwrap();
+        editor.handle_key(Key::Ctrl('^')).unwrap();
+        assert_eq!(editor.buffer.line_at(0), "alpha");
+        assert_eq!(editor.cursor.x, 3);
+        editor.list_buffers();
+        assert_eq!(editor.messages.len(), 2);
+        assert!(editor.messages[1].starts_with("  2 #h +"));
+        editor.messages.clear();
+        // the changed hidden buffer stops :q and comes to the front
+        assert!(editor.execute_command("q").is_err());
+        assert_eq!(editor.buffers.current, 2);
+        assert_eq!(editor.buffer.line_at(1), "eta two");
+        assert!(editor.execute_command("bd").is_err());
+        editor.execute_command("bd!").unwrap();
+        assert_eq!(editor.opened_file(), Some(a.as_path()));
+        assert!(editor.execute_command("b test_buffers_b").is_err());
+        editor.execute_command("q").unwrap();
+        assert!(editor.should_quit());
+        editor.close();
+    }
+
+    #[test]
+    fn test_split_with_a_file_leaves_the_unnamed_buffer_alone() {
+        let path = temp_file_path("test_split_unnamed.txt");
+        fs::write(&path, "file\n").unwrap();
+        let mut editor = Editor::default();
+        editor.buffer.load_str("scratch\n");
+        editor
+            .execute_command(&format!("sp {}", path.display()))
+            .unwrap();
+        assert_eq!(editor.buffer.line_at(0), "file");
+        editor.handle_key(Key::Ctrl('w')).unwrap();
+        editor.handle_key(Key::Char('w')).unwrap();
+        assert_eq!(editor.buffer.line_at(0), "scratch");
+        // :e in one of two windows on the unnamed buffer opens a new one too
+        editor.execute_command("sp").unwrap();
+        editor
+            .execute_command(&format!("e {}", path.display()))
+            .unwrap();
+        editor.handle_key(Key::Ctrl('w')).unwrap();
+        editor.handle_key(Key::Char('w')).unwrap();
+        assert_eq!(editor.buffer.line_at(0), "scratch");
+        editor.close();
+    }
+
+    #[test]
+    fn test_windows_keep_their_cursors_through_edits() {
+        let a = temp_file_path("test_windows_a.txt");
+        let b = temp_file_path("test_windows_b.txt");
+        fs::write(&a, "one\ntwo\nthree\n").unwrap();
+        fs::write(&b, "other\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&a).unwrap();
+        editor.cursor.y = 2;
+        editor.cursor.x = 2;
+        editor.execute_command("sp").unwrap();
+        assert_eq!(editor.windows.layout.ids(), [2, 1]);
+        editor.cursor.y = 0;
+        for key in [Key::Char('i'), Key::Char('\n'), Key::Char('x'), Key::Esc] {
+            editor.handle_key(key).unwrap();
+        }
+        // the lower window still has its cursor on "three"
+        editor.handle_key(Key::Ctrl('w')).unwrap();
+        editor.handle_key(Key::Char('w')).unwrap();
+        assert_eq!(editor.windows.current, 1);
+        assert_eq!((editor.cursor.y, editor.cursor.x), (3, 2));
+        editor
+            .execute_command(&format!("vs {}", b.display()))
+            .unwrap();
+        assert_eq!(editor.buffer.line_at(0), "other");
+        // the window stays, showing the buffer before
+        editor.execute_command("bd").unwrap();
+        assert_eq!(editor.windows.layout.ids(), [2, 3, 1]);
+        assert_eq!(editor.buffer.line_at(3), "three");
+        editor.execute_command("close").unwrap();
+        assert_eq!(editor.windows.current, 2);
+        editor.execute_command("close").unwrap();
+        assert_eq!(editor.windows.current, 1);
+        assert!(editor.execute_command("close").is_err());
+        // the last window quits only when no buffer is changed
+        assert!(editor.execute_command("q").is_err());
+        editor.execute_command("q!").unwrap();
+        assert!(editor.should_quit());
+        editor.close();
+    }
+
+    #[test]
+    fn test_bad_utf8_is_written_back_unchanged() {
+        let path = temp_file_path("test_bad_utf8.txt");
+        fs::write(&path, b"caf\xC3\xA9 \xFF\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&path).unwrap();
+        assert_eq!(editor.buffer.encoding, Encoding::Utf8);
+        assert!(editor.file_info().contains("[1 bad utf-8 sequences]"));
+        editor.buffer.insert_char(&Location { x: 0, y: 0 }, '>');
+        editor.execute_command("w").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b">caf\xC3\xA9 \xFF\n");
+        // the hex view shows the bytes on disk, not replacement characters
+        editor.toggle_hex().unwrap();
+        assert_eq!(editor.hex.as_ref().unwrap().bytes, b">caf\xC3\xA9 \xFF\n");
+        editor.execute_command("w").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b">caf\xC3\xA9 \xFF\n");
+        editor.close();
+    }
+
+    #[test]
+    fn test_leaving_a_buffer_writes_its_swap_file() {
+        let a = temp_file_path("test_leave_swap_a.txt");
+        let b = temp_file_path("test_leave_swap_b.txt");
+        fs::write(&a, "alpha\n").unwrap();
+        fs::write(&b, "beta\n").unwrap();
+        let mut editor = Editor::default();
+        editor.open_file(&a).unwrap();
+        editor
+            .execute_command(&format!("e {}", b.display()))
+            .unwrap();
+        editor.alternate_buffer().unwrap();
+        editor.handle_key(Key::Char('x')).unwrap();
+        editor.alternate_buffer().unwrap();
+        let (_, info) = swap::find(&a).unwrap();
+        assert_eq!(info.body.as_deref(), Some(&b"lpha\n"[..]));
+        editor.close();
+    }
+
+    #[test]
+    fn test_buffers_keep_their_sideways_scroll() {
+        let mut editor = Editor::default();
+        editor.set_option("nowrap").unwrap();
+        editor.resize(Size::new(4, 2));
+        editor.buffer.load_str("abcdefgh\n");
+        editor.cursor.x = 7;
+        editor.update_view();
+        assert_eq!(editor.view.offset_x, 4);
+        // back inside the view, so only a kept offset still shows column 4
+        editor.cursor.x = 5;
+        editor.add_buffer();
+        assert_eq!(editor.view.offset_x, 0);
+        editor.alternate_buffer().unwrap();
+        assert_eq!(editor.view.offset_x, 4);
+    }
+
+    #[test]
+    fn test_close_after_only_and_on_the_last_window() {
+        let mut editor = Editor::default();
+        editor.execute_command("sp").unwrap();
+        editor.execute_command("vs").unwrap();
+        editor.execute_command("only").unwrap();
+        assert_eq!(editor.windows.layout.ids(), [3]);
+        assert!(editor.execute_command("close").is_err());
+        assert!(editor.close_window().is_err());
+        editor.execute_command("sp").unwrap();
+        editor.execute_command("close").unwrap();
+        assert_eq!(editor.windows.layout.ids(), [3]);
+        assert!(editor.windows.others.is_empty());
+        assert!(editor.close_window().is_err());
+        assert!(!editor.should_quit());
+    }
+
+    #[test]
+    fn test_argument_list_jumps_and_readonly() {
+        let a = temp_file_path("test_args_a.txt");
+        let b = temp_file_path("test_args_b.txt");
+        fs::write(&a, "one\ntwo\nfn Main() {}\n").unwrap();
+        fs::write(&b, "x\ny\nz\n").unwrap();
+        let mut editor = Editor::default();
+        editor.set_option("ic").unwrap();
+        let args = args::parse([
+            "-R".to_owned(),
+            "+/main(".to_owned(),
+            a.display().to_string(),
+            format!("{}:3:1", b.display()),
+        ])
+        .unwrap();
+        let Action::Edit(args) = args else {
+            panic!("expected files");
+        };
+        editor.open_args(args).unwrap();
+        assert_eq!((editor.cursor.y, editor.cursor.x), (2, 3));
+        assert!(editor.execute_command("w").is_err());
+        editor.execute_command("next").unwrap();
+        assert_eq!(editor.opened_file(), Some(b.as_path()));
+        assert_eq!(editor.cursor.y, 2);
+        assert!(editor.execute_command("next").is_err());
+        editor.execute_command("args").unwrap();
+        assert_eq!(
+            editor.status(),
+            format!("{} [{}]", a.display(), b.display())
+        );
+        editor.execute_command("prev").unwrap();
+        assert_eq!(editor.buffers.ids(), [1, 2]);
+        editor.close();
+    }
+
     #[test]
     fn test_open_existing_file() {
         let path = temp_file_path("test_open.txt");
@@ -0,0 +1,274 @@
+use std::io::{Error, ErrorKind, Result};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+// every byte decodes to some char and back, so files that are not valid in
+// any of the unicode encodings still round-trip unchanged.
+pub const FALLBACK: Encoding = Encoding::Windows1252;
+
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+            Encoding::Windows1252 => "cp1252",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" | "ucs-2le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" | "ucs-2" => Some(Encoding::Utf16Be),
+            "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Latin1 | Encoding::Windows1252 => &[],
+        }
+    }
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = bytes.chunks_exact(2).map(|pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                char::decode_utf16(units)
+                    .collect::<std::result::Result<String, _>>()
+                    .ok()
+            }
+            Encoding::Latin1 => Some(bytes.iter().map(|&b| b as char).collect()),
+            Encoding::Windows1252 => Some(
+                bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                        _ => b as char,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+    pub fn encode(&self, s: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(s.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(s.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 | Encoding::Windows1252 => s
+                .chars()
+                .map(|c| self.encode_byte(c).ok_or_else(|| self.unrepresentable(c)))
+                .collect(),
+        }
+    }
+    fn encode_byte(&self, c: char) -> Option<u8> {
+        match (self, c as u32) {
+            (Encoding::Latin1, code @ 0..=0xFF) => Some(code as u8),
+            (Encoding::Windows1252, code @ (0..=0x7F | 0xA0..=0xFF)) => Some(code as u8),
+            (Encoding::Windows1252, _) => WINDOWS_1252_HIGH
+                .iter()
+                .position(|&h| h == c)
+                .map(|i| 0x80 + i as u8),
+            _ => None,
+        }
+    }
+    fn unrepresentable(&self, c: char) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "cannot convert {:?} (U+{:04X}) to {}",
+                c,
+                c as u32,
+                self.name()
+            ),
+        )
+    }
+}
+
+// bytes that are not valid utf-8 are held in the text as the private-use
+// chars U+10FF80..U+10FFFF (invalid bytes are always >= 0x80), so they can be
+// written back unchanged and drawn as U+FFFD
+const RAW: u32 = 0x10FF00;
+
+pub fn raw_byte(c: char) -> Option<u8> {
+    (c as u32)
+        .checked_sub(RAW)
+        .filter(|&b| b >= 0x80)
+        .map(|b| b as u8)
+}
+
+fn raw_char(b: u8) -> char {
+    char::from_u32(RAW + b as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+// utf-8 with the raw byte chars turned back into the bytes they stand for
+pub fn encode_raw(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        match raw_byte(c) {
+            Some(b) => out.push(b),
+            None => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out
+}
+
+// utf-16 without a bom is only guessed when most code units have a zero byte
+// on the same side, which is what mostly-ascii text looks like.
+fn guess_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let even_zero = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zero = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    if odd_zero * 2 > pairs && even_zero == 0 {
+        Some(Encoding::Utf16Le)
+    } else if even_zero * 2 > pairs && odd_zero == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+// a file that is not valid utf-8 is still taken for it when it holds at least
+// as many well-formed multibyte characters as broken sequences, which legacy
+// 8-bit text almost never does by accident; a few bad bytes in a utf-8 file
+// should not turn every other character into mojibake
+fn mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut good, mut bad) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        good += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        bad += !chunk.invalid().is_empty() as usize;
+    }
+    good > 0 && good >= bad
+}
+
+pub fn detect(bytes: &[u8]) -> (Encoding, bool) {
+    for enc in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if let Some(rest) = bytes.strip_prefix(enc.bom())
+            && (enc == Encoding::Utf8 || enc.decode(rest).is_some())
+        {
+            return (enc, true);
+        }
+    }
+    // nul bytes are valid utf-8, so the utf-16 guess has to come first
+    if let Some(enc) = guess_utf16(bytes)
+        && enc.decode(bytes).is_some()
+    {
+        return (enc, false);
+    }
+    if std::str::from_utf8(bytes).is_ok() || mostly_utf8(bytes) {
+        return (Encoding::Utf8, false);
+    }
+    (FALLBACK, false)
+}
+
+// the text, its encoding, whether it had a bom and how many invalid utf-8
+// sequences were kept as raw byte chars
+pub fn decode(bytes: &[u8]) -> (String, Encoding, bool, usize) {
+    let (enc, bom) = detect(bytes);
+    let body = if bom {
+        &bytes[enc.bom().len()..]
+    } else {
+        bytes
+    };
+    if enc == Encoding::Utf8 && std::str::from_utf8(body).is_err() {
+        let (mut text, mut bad) = (String::with_capacity(body.len()), 0);
+        for chunk in body.utf8_chunks() {
+            text.push_str(chunk.valid());
+            text.extend(chunk.invalid().iter().map(|&b| raw_char(b)));
+            bad += !chunk.invalid().is_empty() as usize;
+        }
+        return (text, enc, bom, bad);
+    }
+    let text = enc
+        .decode(body)
+        .or_else(|| FALLBACK.decode(body))
+        .unwrap_or_default();
+    (text, enc, bom, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_boms() {
+        assert_eq!(detect(b"\xEF\xBB\xBFhi"), (Encoding::Utf8, true));
+        assert_eq!(detect(b"\xFF\xFEh\0i\0"), (Encoding::Utf16Le, true));
+        assert_eq!(detect(b"\xFE\xFF\0h\0i"), (Encoding::Utf16Be, true));
+        assert_eq!(detect("héllo".as_bytes()), (Encoding::Utf8, false));
+    }
+
+    #[test]
+    fn guesses_utf16_without_bom() {
+        let (text, enc, bom, _) = decode(b"h\0i\0\n\0");
+        assert_eq!(
+            (text.as_str(), enc, bom),
+            ("hi\n", Encoding::Utf16Le, false)
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_round_trips_through_fallback() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let (text, enc, _, bad) = decode(&bytes);
+        assert_eq!((enc, bad), (FALLBACK, 0));
+        assert_eq!(enc.encode(&text).unwrap(), bytes);
+        assert_eq!(
+            Encoding::Latin1
+                .encode(&Encoding::Latin1.decode(&bytes).unwrap())
+                .unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn a_few_bad_bytes_keep_utf8() {
+        let (text, enc, _, bad) = decode("héllo \u{2014} wörld\n".as_bytes());
+        assert_eq!((enc, bad), (Encoding::Utf8, 0));
+        assert_eq!(text, "héllo \u{2014} wörld\n");
+        let mut bytes = "héllo wörld ".as_bytes().to_vec();
+        bytes.push(0xFF);
+        let (text, enc, _, bad) = decode(&bytes);
+        assert_eq!((enc, bad), (Encoding::Utf8, 1));
+        assert_eq!(text.chars().last().and_then(raw_byte), Some(0xFF));
+        assert_eq!(encode_raw(&text), bytes);
+        // latin1 accents are never valid utf-8 sequences
+        assert_eq!(detect(b"caf\xE9 cr\xE8me").0, FALLBACK);
+    }
+
+    #[test]
+    fn encode_rejects_unrepresentable_chars() {
+        assert!(Encoding::Latin1.encode("€").is_err());
+        assert_eq!(Encoding::Windows1252.encode("€").unwrap(), vec![0x80]);
+        assert_eq!(Encoding::Utf16Be.encode("a").unwrap(), vec![0, b'a']);
+    }
+}
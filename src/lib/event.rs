@@ -0,0 +1,192 @@
+use ratatui::termion::event::Event as TermEvent;
+use ratatui::termion::input::TermRead;
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
+use std::io::{self, Read, Result};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::editor::Editor;
+use crate::input::Key;
+use crate::terminal;
+
+pub type Message = Box<dyn FnOnce(&mut Editor) -> Result<()> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    Swap,
+    Autosave,
+    Mapping,
+}
+
+pub enum Event {
+    Key(Key),
+    FocusIn,
+    FocusOut,
+    Resize,
+    Timer(Timer),
+    Message(Message),
+    Error(io::Error),
+    // the input ran out; wait reports it as the end of the loop
+    InputClosed,
+}
+
+// every source (stdin, signals, worker threads) feeds one channel; timers are
+// kept here and turned into events when the channel stays quiet long enough.
+pub struct EventLoop {
+    tx: Sender<Event>,
+    rx: Receiver<Event>,
+    timers: Vec<(Timer, Instant)>,
+}
+
+impl Default for EventLoop {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx,
+            rx,
+            timers: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for EventLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventLoop")
+            .field("timers", &self.timers)
+            .finish()
+    }
+}
+
+impl EventLoop {
+    pub fn sender(&self) -> Sender<Event> {
+        self.tx.clone()
+    }
+    pub fn spawn_input<R: Read + Send + 'static>(&self, input: R) {
+        let tx = self.sender();
+        thread::spawn(move || {
+            for event in input.events() {
+                let event = match event {
+                    Ok(TermEvent::Key(key)) => Event::Key(key.into()),
+                    Ok(TermEvent::Unsupported(seq)) if seq == terminal::FOCUS_IN => Event::FocusIn,
+                    Ok(TermEvent::Unsupported(seq)) if seq == terminal::FOCUS_OUT => {
+                        Event::FocusOut
+                    }
+                    Ok(_) => continue,
+                    Err(e) => Event::Error(e),
+                };
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+            // the loop holds a sender of its own, so the channel alone never
+            // says that stdin is gone
+            let _ = tx.send(Event::InputClosed);
+        });
+    }
+    pub fn watch_resize(&self) -> Result<()> {
+        let mut signals = Signals::new([SIGWINCH])?;
+        let tx = self.sender();
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                if tx.send(Event::Resize).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+    pub fn spawn<F>(&self, work: F)
+    where
+        F: FnOnce() -> Message + Send + 'static,
+    {
+        let tx = self.sender();
+        thread::spawn(move || {
+            let _ = tx.send(Event::Message(work()));
+        });
+    }
+    pub fn set_timer(&mut self, timer: Timer, after: Duration) {
+        self.cancel_timer(timer);
+        self.timers.push((timer, Instant::now() + after));
+    }
+    pub fn cancel_timer(&mut self, timer: Timer) {
+        self.timers.retain(|(t, _)| *t != timer);
+    }
+    pub fn timer_pending(&self, timer: Timer) -> bool {
+        self.timers.iter().any(|(t, _)| *t == timer)
+    }
+    fn take_due_timer(&mut self) -> Option<Timer> {
+        let now = Instant::now();
+        let i = self.timers.iter().position(|(_, due)| *due <= now)?;
+        Some(self.timers.remove(i).0)
+    }
+    pub fn wait(&mut self) -> Option<Event> {
+        loop {
+            if let Some(timer) = self.take_due_timer() {
+                return Some(Event::Timer(timer));
+            }
+            let deadline = self.timers.iter().map(|(_, due)| *due).min();
+            let event = match deadline {
+                Some(due) => match self
+                    .rx
+                    .recv_timeout(due.saturating_duration_since(Instant::now()))
+                {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                },
+                None => self.rx.recv().ok()?,
+            };
+            return match event {
+                Event::InputClosed => None,
+                event => Some(event),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers_fire_in_order_and_can_be_cancelled() {
+        let mut events = EventLoop::default();
+        events.set_timer(Timer::Autosave, Duration::from_millis(20));
+        events.set_timer(Timer::Swap, Duration::from_millis(1));
+        assert!(matches!(events.wait(), Some(Event::Timer(Timer::Swap))));
+        events.cancel_timer(Timer::Autosave);
+        assert!(!events.timer_pending(Timer::Autosave));
+        events.sender().send(Event::Resize).unwrap();
+        assert!(matches!(events.wait(), Some(Event::Resize)));
+    }
+
+    #[test]
+    fn input_and_worker_messages_share_the_loop() {
+        let mut events = EventLoop::default();
+        events.spawn(|| {
+            Box::new(|editor: &mut Editor| {
+                editor.set_status("done".to_owned());
+                Ok(())
+            })
+        });
+        let mut editor = Editor::default();
+        match events.wait() {
+            Some(Event::Message(apply)) => apply(&mut editor).unwrap(),
+            _ => panic!("expected worker message"),
+        }
+        assert_eq!(editor.status(), "done");
+        events.spawn_input(&b"a\x1b[I"[..]);
+        assert!(matches!(events.wait(), Some(Event::Key(Key::Char('a')))));
+        assert!(matches!(events.wait(), Some(Event::FocusIn)));
+    }
+
+    #[test]
+    fn wait_ends_when_the_input_does() {
+        let mut events = EventLoop::default();
+        events.set_timer(Timer::Autosave, Duration::from_secs(60));
+        events.spawn_input(io::empty());
+        assert!(events.wait().is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt, chown};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub mtime: Option<SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+
+impl FileStamp {
+    pub fn new(meta: &fs::Metadata, contents: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        Self {
+            mtime: meta.modified().ok(),
+            len: meta.len(),
+            hash: hasher.finish(),
+        }
+    }
+    pub fn read(path: &Path) -> Result<Self> {
+        let meta = fs::metadata(path)?;
+        let contents = fs::read(path)?;
+        Ok(Self::new(&meta, &contents))
+    }
+    // a matching mtime and size is trusted; otherwise the contents decide, so
+    // a plain touch does not count as a change
+    pub fn changed(&self, path: &Path) -> bool {
+        let Ok(meta) = fs::metadata(path) else {
+            return true;
+        };
+        if meta.modified().ok() == self.mtime && meta.len() == self.len {
+            return false;
+        }
+        match Self::read(path) {
+            Ok(now) => now.len != self.len || now.hash != self.hash,
+            Err(_) => true,
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/ve, falling back to ~/.config/ve
+pub fn config_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(config.join("ve"))
+}
+
+pub fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut resolved = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match fs::read_link(&resolved) {
+            Ok(target) if target.is_absolute() => resolved = target,
+            Ok(target) => {
+                let dir = resolved.parent().unwrap_or(Path::new("")).to_path_buf();
+                resolved = dir.join(target);
+            }
+            Err(_) => break,
+        }
+    }
+    resolved
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("~");
+    PathBuf::from(name)
+}
+
+fn temp_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".ve{}.tmp", std::process::id()));
+    target.with_file_name(name)
+}
+
+// writes to a temp file in the same directory, syncs it and renames it over
+// the target so a failed write never leaves a truncated file behind.
+pub fn write_atomic(path: &Path, contents: &[u8], backup: bool) -> Result<()> {
+    let target = resolve_symlinks(path);
+    let existing = fs::metadata(&target).ok();
+    if backup && existing.is_some() {
+        fs::copy(&target, backup_path(&target))?;
+    }
+    rename_into(
+        &target,
+        contents,
+        final_mode(existing.as_ref()),
+        existing.as_ref(),
+    )
+}
+
+// like write_atomic, but only the owner may read the result, whatever mode
+// the file had; swap and recovery files hold the unsaved text of files that
+// may well be private. they sit in shared directories, so a symlink or
+// anything else planted in their place is refused rather than followed
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Ok(meta) = fs::symlink_metadata(path)
+        && !meta.file_type().is_file()
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a regular file", path.display()),
+        ));
+    }
+    // the temp file is created exclusively and the rename replaces the name
+    // itself, so neither step follows a link that appears in the meantime
+    rename_into(path, contents, 0o600, None)
+}
+
+// existing is the metadata whose mode and ownership the file keeps
+fn rename_into(
+    target: &Path,
+    contents: &[u8],
+    mode: u32,
+    existing: Option<&fs::Metadata>,
+) -> Result<()> {
+    let tmp = temp_path(target);
+    let result = write_temp(&tmp, contents, mode, existing).and_then(|_| fs::rename(&tmp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    if let Some(dir) = target.parent().filter(|d| !d.as_os_str().is_empty()) {
+        // directory fsync makes the rename durable; not every filesystem allows it
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+    Ok(())
+}
+
+fn write_temp(
+    tmp: &Path,
+    contents: &[u8],
+    mode: u32,
+    existing: Option<&fs::Metadata>,
+) -> Result<()> {
+    let mut file = create_temp(tmp, mode)?;
+    file.write_all(contents)?;
+    if let Some(meta) = existing {
+        // only root can give the file away; keeping our own ownership is fine otherwise
+        let _ = chown(tmp, Some(meta.uid()), Some(meta.gid()));
+        // after chown, which clears setuid/setgid, and past the umask the file was created under
+        fs::set_permissions(tmp, meta.permissions())?;
+    }
+    file.sync_all()
+}
+
+// the mode the saved file ends up with; a new file gets the usual 0666 less
+// the umask
+fn final_mode(existing: Option<&fs::Metadata>) -> u32 {
+    existing.map_or(0o666, |meta| meta.permissions().mode() & 0o777)
+}
+
+// the temp file has its final mode before anything is written to it, so the
+// contents of a private file are never readable by others on the way
+fn create_temp(tmp: &Path, mode: u32) -> Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ve_file_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_atomic_preserves_mode() {
+        let dir = temp_dir("mode");
+        let path = dir.join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        write_atomic(&path, b"new", false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o750);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_atomic_keeps_setgid() {
+        let dir = temp_dir("setgid");
+        let path = dir.join("tool");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o2755)).unwrap();
+        write_atomic(&path, b"new", false).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode, 0o2755);
+    }
+
+    #[test]
+    fn temp_file_of_a_private_file_is_private() {
+        let dir = temp_dir("private");
+        let path = dir.join("netrc");
+        fs::write(&path, "secret").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let tmp = temp_path(&path);
+        let meta = fs::metadata(&path).unwrap();
+        let file = create_temp(&tmp, final_mode(Some(&meta))).unwrap();
+        let mode = file.metadata().unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        fs::remove_file(&tmp).unwrap();
+        write_atomic(&path, b"new secret", false).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn write_private_refuses_symlinks() {
+        let dir = temp_dir("private_link");
+        let target = dir.join("victim");
+        let link = dir.join("file.swp");
+        fs::write(&target, "keep").unwrap();
+        symlink("victim", &link).unwrap();
+        assert!(write_private(&link, b"swap").is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "keep");
+        fs::remove_file(&link).unwrap();
+        write_private(&link, b"swap").unwrap();
+        assert_eq!(fs::read_to_string(&link).unwrap(), "swap");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn write_atomic_follows_symlinks() {
+        let dir = temp_dir("link");
+        let target = dir.join("real.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old").unwrap();
+        symlink("real.txt", &link).unwrap();
+        write_atomic(&link, b"new", false).unwrap();
+        assert!(
+            fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[test]
+    fn file_stamp_detects_content_changes_only() {
+        let dir = temp_dir("stamp");
+        let path = dir.join("watched.txt");
+        fs::write(&path, "one").unwrap();
+        let stamp = FileStamp::read(&path).unwrap();
+        assert!(!stamp.changed(&path));
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert!(!stamp.changed(&path));
+        fs::write(&path, "two").unwrap();
+        assert!(stamp.changed(&path));
+        fs::remove_file(&path).unwrap();
+        assert!(stamp.changed(&path));
+    }
+
+    #[test]
+    fn write_atomic_keeps_backup() {
+        let dir = temp_dir("backup");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        write_atomic(&path, b"new", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("notes.txt~")).unwrap(), "old");
+    }
+}
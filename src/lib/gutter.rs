@@ -0,0 +1,106 @@
+use ratatui::buffer::Buffer as Screen;
+
+use crate::theme::Theme;
+use crate::view::View;
+
+// one column of the gutter; signs and diagnostics markers go here next to
+// the line numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    // absolute, relative to the cursor line, or both (hybrid: the cursor
+    // line shows its own number, the others their distance)
+    Number { absolute: bool, relative: bool },
+}
+
+impl Column {
+    // like vim's numberwidth=4: at least three digits, then a blank
+    fn width(&self, line_count: usize) -> usize {
+        match self {
+            Column::Number { .. } => line_count.max(1).to_string().len().max(3) + 1,
+        }
+    }
+    fn text(&self, y: usize, cursor_y: usize, width: usize) -> String {
+        match *self {
+            Column::Number { absolute, relative } => {
+                let distance = y.abs_diff(cursor_y);
+                match (absolute, relative) {
+                    (true, true) if distance == 0 => format!("{:<w$} ", y + 1, w = width - 1),
+                    (_, true) => format!("{:>w$} ", distance, w = width - 1),
+                    _ => format!("{:>w$} ", y + 1, w = width - 1),
+                }
+            }
+        }
+    }
+}
+
+// the columns left of the text of one window, laid out side by side
+#[derive(Debug, Default, Clone)]
+pub struct Gutter {
+    columns: Vec<(Column, usize)>,
+    cursor_y: usize,
+}
+
+impl Gutter {
+    pub fn new(view: &View, line_count: usize, cursor_y: usize) -> Self {
+        let mut columns = Vec::new();
+        if view.number || view.relativenumber {
+            columns.push(Column::Number {
+                absolute: view.number,
+                relative: view.relativenumber,
+            });
+        }
+        Self {
+            columns: columns
+                .into_iter()
+                .map(|c| (c, c.width(line_count)))
+                .collect(),
+            cursor_y,
+        }
+    }
+    pub fn width(&self) -> u16 {
+        self.columns.iter().map(|(_, w)| w).sum::<usize>() as u16
+    }
+    // the gutter of the first screen row of line y; continuation rows of a
+    // wrapped line are left blank
+    pub fn render(&self, screen: &mut Screen, x: u16, row: u16, y: usize, theme: &Theme) {
+        let group = if y == self.cursor_y {
+            "CursorLineNr"
+        } else {
+            "LineNr"
+        };
+        let style = theme.style(group);
+        let mut x = x;
+        for &(column, width) in &self.columns {
+            screen.set_stringn(x, row, column.text(y, self.cursor_y, width), width, style);
+            x += width as u16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_modes() {
+        let both = Column::Number {
+            absolute: true,
+            relative: true,
+        };
+        let relative = Column::Number {
+            absolute: false,
+            relative: true,
+        };
+        let absolute = Column::Number {
+            absolute: true,
+            relative: false,
+        };
+        assert_eq!(absolute.width(99), 4);
+        assert_eq!(absolute.width(12345), 6);
+        assert_eq!(absolute.text(9, 4, 4), " 10 ");
+        assert_eq!(relative.text(9, 4, 4), "  5 ");
+        assert_eq!(relative.text(4, 4, 4), "  0 ");
+        assert_eq!(both.text(4, 4, 4), "5   ");
+        assert_eq!(both.text(2, 4, 4), "  2 ");
+    }
+}
@@ -0,0 +1,275 @@
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer as Screen;
+use ratatui::layout::Position;
+use std::io::{Error, ErrorKind, Result};
+use unicode_width::UnicodeWidthStr;
+
+use crate::editor::Editor;
+use crate::input::{self, Key};
+
+// drives an editor headlessly: keys go straight to Editor::handle_key and
+// every key is followed by a draw into a ratatui TestBackend
+pub struct Harness {
+    pub editor: Editor,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    pub fn new(width: u16, height: u16) -> Self {
+        let Ok(terminal) = Terminal::new(TestBackend::new(width, height));
+        let mut harness = Self {
+            editor: Editor::default(),
+            terminal,
+        };
+        harness.draw();
+        harness
+    }
+    pub fn with_text(width: u16, height: u16, text: &str) -> Self {
+        let mut harness = Self::new(width, height);
+        harness.editor.buffer.load_str(text);
+        harness.draw();
+        harness
+    }
+    pub fn draw(&mut self) {
+        let editor = &mut self.editor;
+        let Ok(_) = self.terminal.draw(|frame| editor.draw(frame));
+    }
+    pub fn press(&mut self, key: Key) -> &mut Self {
+        if let Err(e) = self.editor.handle_key(key) {
+            self.editor.set_status(e.to_string());
+        }
+        self.draw();
+        self
+    }
+    pub fn type_str(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            self.press(Key::Char(c));
+        }
+        self
+    }
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.terminal.backend_mut().resize(width, height);
+        self.draw();
+    }
+    pub fn screen(&self) -> Vec<String> {
+        screen_rows(self.terminal.backend().buffer())
+    }
+    pub fn cursor(&self) -> Option<Position> {
+        self.editor.screen_cursor
+    }
+}
+
+// one string per row with trailing blanks trimmed; the cells hidden behind a
+// wide character are skipped so the rows read like the terminal shows them
+pub fn screen_rows(screen: &Screen) -> Vec<String> {
+    let area = screen.area;
+    (area.top()..area.bottom())
+        .map(|y| {
+            let mut row = String::new();
+            let mut hidden = 0;
+            for x in area.left()..area.right() {
+                if hidden > 0 {
+                    hidden -= 1;
+                    continue;
+                }
+                let symbol = screen[(x, y)].symbol();
+                hidden = symbol.width().saturating_sub(1);
+                row.push_str(symbol);
+            }
+            row.trim_end().to_owned()
+        })
+        .collect()
+}
+
+// a replay script: optional `size WxH`, `cursor LINE:COL` (1-based) and
+// `mode NAME` lines, then `--- text`, `--- keys`, `--- buffer` and
+// `--- screen` sections. keys are in vim notation and may span lines; the
+// screen section may leave out trailing blank rows. `#` starts a comment
+// outside the sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub size: (u16, u16),
+    pub text: Option<String>,
+    pub keys: String,
+    pub buffer: Option<Vec<String>>,
+    pub cursor: Option<(usize, usize)>,
+    pub mode: Option<String>,
+    pub screen: Option<Vec<String>>,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            size: (40, 10),
+            text: None,
+            keys: String::new(),
+            buffer: None,
+            cursor: None,
+            mode: None,
+            screen: None,
+        }
+    }
+}
+
+fn script_error(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut script = Script::default();
+        let mut section: Option<(&str, Vec<&str>)> = None;
+        for (i, line) in source.lines().enumerate() {
+            let n = i + 1;
+            if let Some(name) = line.strip_prefix("--- ") {
+                if let Some((name, body)) = section.take() {
+                    script.set_section(name, body);
+                }
+                match name.trim() {
+                    name @ ("text" | "keys" | "buffer" | "screen") => {
+                        section = Some((name, Vec::new()))
+                    }
+                    other => return Err(script_error(n, &format!("unknown section {}", other))),
+                }
+                continue;
+            }
+            if let Some((_, body)) = &mut section {
+                body.push(line);
+                continue;
+            }
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "size" => {
+                    script.size = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or_else(|| script_error(n, "size must look like 40x10"))?;
+                }
+                "cursor" => {
+                    let cursor = value
+                        .split_once(':')
+                        .and_then(|(l, c)| Some((l.parse().ok()?, c.parse().ok()?)))
+                        .ok_or_else(|| script_error(n, "cursor must look like 1:1"))?;
+                    script.cursor = Some(cursor);
+                }
+                "mode" => script.mode = Some(value.to_owned()),
+                _ => return Err(script_error(n, &format!("unknown setting {}", key))),
+            }
+        }
+        if let Some((name, body)) = section.take() {
+            script.set_section(name, body);
+        }
+        Ok(script)
+    }
+    fn set_section(&mut self, name: &str, body: Vec<&str>) {
+        let lines = body.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        match name {
+            "text" => self.text = Some(lines.iter().map(|l| format!("{}\n", l)).collect()),
+            "keys" => self.keys = lines.concat(),
+            "buffer" => self.buffer = Some(lines),
+            _ => self.screen = Some(lines),
+        }
+    }
+    // replays the keys and returns every expectation that did not hold
+    pub fn failures(&self) -> Vec<String> {
+        let (width, height) = self.size;
+        let mut ui = match &self.text {
+            Some(text) => Harness::with_text(width, height, text),
+            None => Harness::new(width, height),
+        };
+        for key in input::parse_keys(&self.keys) {
+            ui.press(key);
+        }
+        let mut failures = Vec::new();
+        if let Some(expected) = &self.buffer {
+            let actual = (0..ui.editor.buffer.line_count())
+                .map(|y| ui.editor.buffer.line_at(y).to_owned())
+                .collect::<Vec<_>>();
+            if &actual != expected {
+                failures.push(format!("buffer: expected {:?}, got {:?}", expected, actual));
+            }
+        }
+        if let Some((line, col)) = self.cursor {
+            let actual = (ui.editor.cursor.y + 1, ui.editor.cursor.x + 1);
+            if actual != (line, col) {
+                failures.push(format!(
+                    "cursor: expected {}:{}, got {}:{}",
+                    line, col, actual.0, actual.1
+                ));
+            }
+        }
+        if let Some(expected) = &self.mode {
+            let actual = format!("{:?}", ui.editor.get_mode()).to_lowercase();
+            if &actual != expected {
+                failures.push(format!("mode: expected {}, got {}", expected, actual));
+            }
+        }
+        if let Some(expected) = &self.screen {
+            let mut expected = expected
+                .iter()
+                .map(|l| l.trim_end().to_owned())
+                .collect::<Vec<_>>();
+            expected.resize(height as usize, String::new());
+            let actual = ui.screen();
+            if actual != expected {
+                failures.push(format!(
+                    "screen: expected\n{}\ngot\n{}",
+                    expected.join("\n"),
+                    actual.join("\n")
+                ));
+            }
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_shows_up_on_screen() {
+        let mut ui = Harness::new(20, 4);
+        ui.type_str("ihello")
+            .press(Key::Char('\n'))
+            .type_str("world");
+        assert_eq!(ui.screen(), vec!["hello", "world", "", ""]);
+        assert_eq!(ui.cursor(), Some(Position::new(5, 1)));
+        ui.press(Key::Esc).type_str(":set ff?\n");
+        assert_eq!(ui.screen()[3], "ff=unix");
+    }
+
+    #[test]
+    fn view_follows_the_cursor_within_the_viewport() {
+        let text: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let mut ui = Harness::with_text(12, 4, &text);
+        for _ in 0..5 {
+            ui.press(Key::Down);
+        }
+        assert_eq!(ui.screen(), vec!["line 4", "line 5", "line 6", ""]);
+        assert_eq!(ui.cursor(), Some(Position::new(0, 2)));
+        ui.resize(12, 8);
+        assert_eq!(ui.screen()[0], "line 4");
+        assert_eq!(ui.screen()[6], "line 10");
+    }
+
+    #[test]
+    fn scripts_parse_settings_and_sections() {
+        let script = Script::parse(
+            "# comment\nsize 10x3\ncursor 1:2\n--- text\nab\n--- keys\nx\n<Esc>\n--- buffer\nb\n",
+        )
+        .unwrap();
+        assert_eq!(script.size, (10, 3));
+        assert_eq!(script.text.as_deref(), Some("ab\n"));
+        assert_eq!(script.keys, "x<Esc>");
+        assert_eq!(script.buffer, Some(vec!["b".to_owned()]));
+        assert_eq!(script.failures(), vec!["cursor: expected 1:2, got 1:1"]);
+        let err = Script::parse("size big\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: size must look like 40x10");
+    }
+}
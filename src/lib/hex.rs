@@ -0,0 +1,216 @@
+use ratatui::buffer::Buffer as Screen;
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::widgets::Widget;
+
+use crate::encoding::Encoding;
+
+pub const BYTES_PER_ROW: usize = 16;
+const SNIFF_LEN: usize = 8192;
+const ADDRESS_WIDTH: usize = 10;
+
+// only the first SNIFF_LEN bytes are looked at, so opening a big file does
+// not cost a pass over all of it
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if !head.contains(&0) {
+        return false;
+    }
+    // utf-16 text is full of nul bytes too; the window may end half way
+    // through a surrogate pair
+    let shorter = &head[..head.len().saturating_sub(2)];
+    let utf16 = [head, shorter].into_iter().any(|h| {
+        matches!(
+            crate::encoding::detect(h).0,
+            Encoding::Utf16Le | Encoding::Utf16Be
+        )
+    });
+    !utf16
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HexCursor {
+    pub offset: usize,
+    pub low_nibble: bool,
+}
+
+impl HexCursor {
+    pub fn move_left(&mut self) {
+        if self.low_nibble {
+            self.low_nibble = false;
+        } else if self.offset > 0 {
+            self.offset -= 1;
+            self.low_nibble = true;
+        }
+    }
+    // one past the last byte is allowed so the file can be extended
+    pub fn move_right(&mut self, len: usize) {
+        if !self.low_nibble && self.offset < len {
+            self.low_nibble = true;
+        } else if self.offset < len {
+            self.offset += 1;
+            self.low_nibble = false;
+        }
+    }
+    pub fn move_up(&mut self) {
+        self.offset = self.offset.saturating_sub(BYTES_PER_ROW);
+    }
+    pub fn move_down(&mut self, len: usize) {
+        if self.offset + BYTES_PER_ROW <= len {
+            self.offset += BYTES_PER_ROW;
+        }
+    }
+    pub fn row(&self) -> usize {
+        self.offset / BYTES_PER_ROW
+    }
+    pub fn column(&self) -> usize {
+        let i = self.offset % BYTES_PER_ROW;
+        let gap = if i >= BYTES_PER_ROW / 2 { 1 } else { 0 };
+        ADDRESS_WIDTH + i * 3 + gap + self.low_nibble as usize
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct HexEditor {
+    pub bytes: Vec<u8>,
+    pub cursor: HexCursor,
+    pub offset_row: usize,
+    pub modified: bool,
+    pub changes: usize,
+}
+
+impl HexEditor {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            ..Self::default()
+        }
+    }
+    pub fn set_nibble(&mut self, digit: u8) {
+        debug_assert!(digit < 16, "nibble out of range");
+        if self.cursor.offset == self.bytes.len() {
+            self.bytes.push(0);
+        }
+        let byte = &mut self.bytes[self.cursor.offset];
+        *byte = if self.cursor.low_nibble {
+            (*byte & 0xF0) | digit
+        } else {
+            (*byte & 0x0F) | (digit << 4)
+        };
+        self.modified = true;
+        self.changes += 1;
+        self.cursor.move_right(self.bytes.len());
+    }
+    pub fn scroll(&mut self, rows: usize) {
+        let row = self.cursor.row();
+        if row < self.offset_row {
+            self.offset_row = row;
+        } else if row >= self.offset_row + rows {
+            self.offset_row = row + 1 - rows;
+        }
+    }
+    pub fn format_row(&self, row: usize) -> String {
+        let start = row * BYTES_PER_ROW;
+        let chunk =
+            &self.bytes[start.min(self.bytes.len())..(start + BYTES_PER_ROW).min(self.bytes.len())];
+        let mut out = format!("{:08x}: ", start);
+        for i in 0..BYTES_PER_ROW {
+            if i == BYTES_PER_ROW / 2 {
+                out.push(' ');
+            }
+            match chunk.get(i) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
+            }
+        }
+        out.push(' ');
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out
+    }
+    pub fn cursor_position(&self) -> (u16, u16) {
+        let x = self.cursor.column() as u16;
+        let y = (self.cursor.row() - self.offset_row) as u16;
+        (x, y)
+    }
+}
+
+impl Widget for &HexEditor {
+    fn render(self, area: Rect, screen: &mut Screen) {
+        let total_rows = self.bytes.len() / BYTES_PER_ROW + 1;
+        let end_row = (self.offset_row + area.height as usize).min(total_rows);
+        for (i, row) in (self.offset_row..end_row).enumerate() {
+            screen.set_stringn(
+                area.x,
+                area.y + i as u16,
+                self.format_row(row),
+                area.width as usize,
+                Style::default(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_binary_content() {
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\0\0\0"));
+        assert!(!is_binary(b"plain text\n"));
+        assert!(!is_binary(b"\xFF\xFEh\0i\0"));
+        // an emoji straddling the end of the window is still utf-16
+        let mut utf16 = b"\xFF\xFE".to_vec();
+        utf16.extend(
+            "a".repeat(SNIFF_LEN / 2 - 2)
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+        utf16.extend("😀 and more".encode_utf16().flat_map(u16::to_le_bytes));
+        assert!(!is_binary(&utf16));
+        // past the window, nothing counts
+        let mut late = vec![b'a'; SNIFF_LEN];
+        late.push(0);
+        assert!(!is_binary(&late));
+    }
+
+    #[test]
+    fn nibbles_overwrite_bytes() {
+        let mut hex = HexEditor::new(vec![0x00, 0xff]);
+        hex.set_nibble(0xa);
+        hex.set_nibble(0xb);
+        assert_eq!(hex.bytes, vec![0xab, 0xff]);
+        assert_eq!(
+            hex.cursor,
+            HexCursor {
+                offset: 1,
+                low_nibble: false
+            }
+        );
+        hex.cursor.move_right(hex.bytes.len());
+        hex.set_nibble(0x1);
+        assert_eq!(hex.bytes, vec![0xab, 0xf1]);
+        // typing past the end appends a byte
+        hex.set_nibble(0x7);
+        assert_eq!(hex.bytes, vec![0xab, 0xf1, 0x70]);
+    }
+
+    #[test]
+    fn formats_rows_with_ascii_column() {
+        let hex = HexEditor::new(b"Hello, world!\n\0\x01xyz".to_vec());
+        assert_eq!(
+            hex.format_row(0),
+            "00000000: 48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  Hello, world!..."
+        );
+        assert_eq!(
+            hex.format_row(1),
+            format!("00000010: 78 79 7a {}xyz", " ".repeat(41))
+        );
+    }
+}
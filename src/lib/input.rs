@@ -0,0 +1,158 @@
+use ratatui::termion::event::Key as TermKey;
+
+// the keys the editor understands, independent of the terminal library that
+// produced them; enter and tab arrive as Char('\n') and Char('\t')
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Alt(char),
+    Backspace,
+    Delete,
+    Insert,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    BackTab,
+    F(u8),
+    Esc,
+    Null,
+}
+
+impl From<TermKey> for Key {
+    fn from(key: TermKey) -> Self {
+        match key {
+            TermKey::Char(c) => Key::Char(c),
+            TermKey::Ctrl(c) => Key::Ctrl(c),
+            TermKey::Alt(c) => Key::Alt(c),
+            TermKey::Backspace => Key::Backspace,
+            TermKey::Delete => Key::Delete,
+            TermKey::Insert => Key::Insert,
+            TermKey::Left | TermKey::ShiftLeft | TermKey::AltLeft | TermKey::CtrlLeft => Key::Left,
+            TermKey::Right | TermKey::ShiftRight | TermKey::AltRight | TermKey::CtrlRight => {
+                Key::Right
+            }
+            TermKey::Up | TermKey::ShiftUp | TermKey::AltUp | TermKey::CtrlUp => Key::Up,
+            TermKey::Down | TermKey::ShiftDown | TermKey::AltDown | TermKey::CtrlDown => Key::Down,
+            TermKey::Home | TermKey::CtrlHome => Key::Home,
+            TermKey::End | TermKey::CtrlEnd => Key::End,
+            TermKey::PageUp => Key::PageUp,
+            TermKey::PageDown => Key::PageDown,
+            TermKey::BackTab => Key::BackTab,
+            TermKey::F(n) => Key::F(n),
+            TermKey::Esc => Key::Esc,
+            _ => Key::Null,
+        }
+    }
+}
+
+fn named_key(name: &str) -> Option<Key> {
+    let lower = name.to_ascii_lowercase();
+    if let Some((modifier, rest)) = lower.split_once('-')
+        && let Some(c) = single_char(&name[modifier.len() + 1..]).or(single_char(rest))
+    {
+        return match modifier {
+            "c" => Some(Key::Ctrl(c.to_ascii_lowercase())),
+            "a" | "m" => Some(Key::Alt(c)),
+            _ => None,
+        };
+    }
+    let key = match lower.as_str() {
+        "esc" => Key::Esc,
+        "cr" | "enter" | "return" | "nl" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "s-tab" => Key::BackTab,
+        "bs" | "backspace" => Key::Backspace,
+        "del" | "delete" => Key::Delete,
+        "insert" | "ins" => Key::Insert,
+        "space" => Key::Char(' '),
+        "lt" => Key::Char('<'),
+        "bar" => Key::Char('|'),
+        "bslash" => Key::Char('\\'),
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "nul" => Key::Null,
+        _ => Key::F(lower.strip_prefix('f')?.parse().ok()?),
+    };
+    Some(key)
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+// vim key notation: plain characters stand for themselves and <...> names a
+// special key, e.g. "ihello<Esc>:w<CR>" or "<C-w>v"; anything in angle
+// brackets that is not a key name is taken literally, like vim does
+pub fn parse_keys(notation: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some(end) = rest.find('>')
+            && let Some(key) = named_key(&rest[1..end])
+        {
+            keys.push(key);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        keys.push(Key::Char(c));
+        rest = &rest[c.len_utf8()..];
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn termion_keys_convert() {
+        assert_eq!(Key::from(TermKey::Char('\n')), Key::Char('\n'));
+        assert_eq!(Key::from(TermKey::Ctrl('s')), Key::Ctrl('s'));
+        assert_eq!(Key::from(TermKey::ShiftLeft), Key::Left);
+        assert_eq!(Key::from(TermKey::F(5)), Key::F(5));
+    }
+
+    #[test]
+    fn parses_vim_key_notation() {
+        assert_eq!(
+            parse_keys("ia<Esc>:w<CR>"),
+            vec![
+                Key::Char('i'),
+                Key::Char('a'),
+                Key::Esc,
+                Key::Char(':'),
+                Key::Char('w'),
+                Key::Char('\n'),
+            ]
+        );
+        assert_eq!(
+            parse_keys("<C-W><c-s><M-x><F12><lt><Space>"),
+            vec![
+                Key::Ctrl('w'),
+                Key::Ctrl('s'),
+                Key::Alt('x'),
+                Key::F(12),
+                Key::Char('<'),
+                Key::Char(' '),
+            ]
+        );
+        // not a key name, so the brackets are typed as they are
+        assert_eq!(parse_keys("<x>").len(), 3);
+        assert_eq!(parse_keys("a<b"), parse_keys("a<lt>b"));
+    }
+}
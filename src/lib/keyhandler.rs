@@ -1,6 +1,14 @@
-use ratatui::termion::event::Key;
+use crate::buffer::Location;
+use crate::command;
+use crate::event::Timer;
+use crate::input::Key;
+use crate::keymap::{Lookup, MapMode, Mapping};
+use crate::window::Stack;
 use std::io::Result;
 
+// how deep a mapping may expand into other mappings, like vim's maxmapdepth
+const MAX_MAP_DEPTH: usize = 1000;
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     #[default]
@@ -20,20 +28,139 @@ impl<'a> KeyHandler<'a> {
         Self { editor }
     }
 
-    pub fn process_key(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
+    // runs the typeahead through the keymap of the current mode. keys that
+    // could still start a longer mapping stay queued until more keys come or
+    // the timeout fires
+    pub fn process_typeahead(&mut self, timed_out: bool) -> Result<()> {
+        while !self.editor.typeahead.is_empty() {
+            let lookup = match self.map_mode() {
+                Some(mode) => self
+                    .editor
+                    .keymap
+                    .lookup(mode, &self.editor.typeahead, !timed_out),
+                None => Lookup::None,
+            };
+            match lookup {
+                Lookup::Pending => {
+                    let timeout = self.editor.keymap.timeout();
+                    self.editor.events.set_timer(Timer::Mapping, timeout);
+                    return Ok(());
+                }
+                Lookup::Match(mapping) => {
+                    self.editor.typeahead.drain(..mapping.lhs.len());
+                    if let Err(e) = self.expand(&mapping, 0) {
+                        self.editor.typeahead.clear();
+                        self.editor.set_status(e.to_string());
+                    }
+                }
+                Lookup::None => {
+                    let key = self.editor.typeahead.remove(0);
+                    self.process_key(key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    // the rhs of a mapping; unless it is a noremap its keys are looked up
+    // again, except for a leading copy of the lhs (like vim, so "nmap x xl"
+    // does not loop)
+    fn expand(&mut self, mapping: &Mapping, depth: usize) -> Result<()> {
+        if depth >= MAX_MAP_DEPTH {
+            return Err(command::invalid("recursive mapping".to_owned()));
+        }
+        let mut keys = mapping.rhs.clone();
+        if mapping.noremap || keys.starts_with(&mapping.lhs) {
+            let raw = if mapping.noremap {
+                keys.len()
+            } else {
+                mapping.lhs.len()
+            };
+            for key in keys.drain(..raw) {
+                self.process_key(key)?;
+            }
+        }
+        while !keys.is_empty() {
+            let lookup = self
+                .map_mode()
+                .map(|mode| self.editor.keymap.lookup(mode, &keys, false));
+            match lookup {
+                Some(Lookup::Match(inner)) => {
+                    keys.drain(..inner.lhs.len());
+                    self.expand(&inner, depth + 1)?;
+                }
+                _ => {
+                    let key = keys.remove(0);
+                    self.process_key(key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    // the keymap in use; the key after a prefix like 'g' is never mapped
+    fn map_mode(&self) -> Option<MapMode> {
+        if !self.editor.messages.is_empty()
+            || self.editor.prompt.is_some()
+            || self.editor.pending.is_some()
+        {
+            return None;
+        }
         match self.editor.get_mode() {
-            Mode::Normal => self.handle_normal(key, stdout),
-            Mode::Edit => self.handle_edit(key, stdout),
-            Mode::Replace => self.handle_replace(key, stdout),
-            Mode::Command => self.handle_command(key, stdout),
-            Mode::Visual => self.handle_visual(key, stdout),
+            Mode::Normal if self.editor.hex.is_some() => None,
+            Mode::Normal => Some(MapMode::Normal),
+            Mode::Visual => Some(MapMode::Visual),
+            Mode::Edit => Some(MapMode::Insert),
+            Mode::Replace | Mode::Command => None,
         }
     }
-    fn handle_normal(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
+    pub fn process_key(&mut self, key: Key) -> Result<()> {
+        if !self.editor.messages.is_empty() {
+            self.editor.messages.clear();
+            return Ok(());
+        }
+        if self.editor.prompt.is_some() {
+            if let Err(e) = self.editor.answer_prompt(key == Key::Char('y')) {
+                self.editor.set_status(e.to_string());
+            }
+            return Ok(());
+        }
+        match self.editor.get_mode() {
+            Mode::Normal if self.editor.hex.is_some() && self.editor.pending.is_none() => {
+                self.handle_hex(key)
+            }
+            Mode::Normal => self.handle_normal(key),
+            Mode::Edit => self.handle_edit(key),
+            Mode::Replace => self.handle_replace(key),
+            Mode::Command => self.handle_command(key),
+            Mode::Visual => self.handle_visual(key),
+        }
+    }
+    fn handle_normal(&mut self, key: Key) -> Result<()> {
+        match self.editor.pending.take() {
+            Some(Key::Char('g')) => {
+                match key {
+                    Key::Char('j') | Key::Down => self.editor.move_visual(true),
+                    Key::Char('k') | Key::Up => self.editor.move_visual(false),
+                    _ => {}
+                }
+                self.editor.update_view();
+                return Ok(());
+            }
+            Some(Key::Ctrl('w')) => {
+                if let Err(e) = self.handle_window(key) {
+                    self.editor.set_status(e.to_string());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
         match key {
-            Key::Char(':') => self.editor.set_mode(Mode::Command),
+            Key::Char('g') | Key::Ctrl('w') => self.editor.pending = Some(key),
+            Key::Char(':') => {
+                self.editor.command_line.clear();
+                self.editor.set_mode(Mode::Command);
+            }
             Key::Char('a') => {
-                let line_len = self.editor.buffer.line_at(self.editor.cursor.y).len();
+                let line_len = self.editor.buffer.lines[self.editor.cursor.y].grapheme_len();
                 if self.editor.cursor.x < line_len {
                     self.editor.cursor.x += 1;
                 } else if self.editor.cursor.y + 1 < self.editor.buffer.line_count() {
@@ -48,7 +175,6 @@ impl<'a> KeyHandler<'a> {
             Key::Char('x') => {
                 self.editor.delete_under_cursor();
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Char('r') => {
                 self.editor.set_mode(Mode::Replace);
@@ -56,7 +182,6 @@ impl<'a> KeyHandler<'a> {
             Key::Char('s') => {
                 self.editor.delete_under_cursor();
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
                 self.editor.set_mode(Mode::Edit);
             }
             Key::Char('b') => {}
@@ -67,55 +192,123 @@ impl<'a> KeyHandler<'a> {
             Key::Char('v') => {
                 self.editor.set_mode(Mode::Visual);
             }
+            Key::Char('h') => return self.handle_normal(Key::Left),
+            Key::Char('j') => return self.handle_normal(Key::Down),
+            Key::Char('k') => return self.handle_normal(Key::Up),
+            Key::Char('l') => return self.handle_normal(Key::Right),
             Key::Left | Key::Right | Key::Up | Key::Down => {
                 self.editor.handle_cursor(key)?;
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Ctrl('s') => {
-                if let Some(path) = self.editor.current_file() {
-                    self.editor.write_file(path)?;
-                } else {
-                    /* how to handle? */
+                if let Err(e) = self.editor.save(None, false) {
+                    self.editor.set_status(e.to_string());
                 }
             }
             Key::Ctrl('q') => {
-                std::process::exit(0);
+                if let Err(e) = self.editor.quit() {
+                    self.editor.set_status(e.to_string());
+                }
+            }
+            // terminals send ctrl-^ as ctrl-6
+            Key::Ctrl('^' | '6') => {
+                if let Err(e) = self.editor.alternate_buffer() {
+                    self.editor.set_status(e.to_string());
+                }
             }
             _ => {}
         }
         Ok(())
     }
-    fn handle_edit(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
+    // the key after ctrl-w
+    fn handle_window(&mut self, key: Key) -> Result<()> {
+        let current = self.editor.windows.current;
+        let direction = match key {
+            Key::Char(c @ ('h' | 'j' | 'k' | 'l')) | Key::Ctrl(c @ ('h' | 'j' | 'k' | 'l')) => {
+                Some(c)
+            }
+            Key::Left => Some('h'),
+            Key::Down => Some('j'),
+            Key::Up => Some('k'),
+            Key::Right => Some('l'),
+            _ => None,
+        };
+        if let Some(direction) = direction {
+            if let Some(id) = self.editor.windows.neighbour(direction) {
+                self.editor.enter_window(id)?;
+            }
+            return Ok(());
+        }
+        match key {
+            Key::Char('s' | 'S') | Key::Ctrl('s') => self.editor.split_window(Stack::Rows)?,
+            Key::Char('v') | Key::Ctrl('v') => self.editor.split_window(Stack::Columns)?,
+            Key::Char('w') | Key::Ctrl('w') => {
+                self.editor.enter_window(self.editor.windows.cycle(1))?
+            }
+            Key::Char('W') => self.editor.enter_window(self.editor.windows.cycle(-1))?,
+            Key::Char('c') => self.editor.execute_command("close")?,
+            Key::Char('q') => self.editor.execute_command("q")?,
+            Key::Char('o') => self.editor.only_window(),
+            Key::Char('+') => _ = self.editor.windows.layout.resize(current, Stack::Rows, 1),
+            Key::Char('-') => _ = self.editor.windows.layout.resize(current, Stack::Rows, -1),
+            Key::Char('>') => {
+                _ = self
+                    .editor
+                    .windows
+                    .layout
+                    .resize(current, Stack::Columns, 1)
+            }
+            Key::Char('<') => {
+                _ = self
+                    .editor
+                    .windows
+                    .layout
+                    .resize(current, Stack::Columns, -1)
+            }
+            Key::Char('=') => self.editor.windows.layout.equalize(),
+            _ => {}
+        }
+        Ok(())
+    }
+    fn handle_hex(&mut self, key: Key) -> Result<()> {
+        let Some(hex) = self.editor.hex.as_mut() else {
+            return Ok(());
+        };
+        let len = hex.bytes.len();
+        match key {
+            Key::Char(':') | Key::Ctrl(_) => return self.handle_normal(key),
+            Key::Char(c) if c.is_ascii_hexdigit() => {
+                hex.set_nibble(c.to_digit(16).unwrap_or(0) as u8);
+            }
+            Key::Left | Key::Backspace => hex.cursor.move_left(),
+            Key::Right => hex.cursor.move_right(len),
+            Key::Up => hex.cursor.move_up(),
+            Key::Down => hex.cursor.move_down(len),
+            _ => {}
+        }
+        self.editor.update_view();
+        Ok(())
+    }
+    fn handle_edit(&mut self, key: Key) -> Result<()> {
         match key {
             Key::Char('\n') => {
-                let line = self.editor.buffer.line_at(self.editor.cursor.y).to_owned();
-                let byte_offset =
-                    self.editor.buffer.lines[self.editor.cursor.y].graphemes[self.editor.cursor.x];
-                let (left, right) = line.split_at(byte_offset);
-                self.editor.buffer.lines[self.editor.cursor.y] =
-                    crate::buffer::Line::from_string(left.to_owned());
-                self.editor.buffer.lines.insert(
-                    self.editor.cursor.y + 1,
-                    crate::buffer::Line::from_string(right.to_owned()),
-                );
+                self.editor
+                    .buffer
+                    .split_line(&(crate::buffer::Location::from(self.editor.cursor)));
                 self.editor.cursor.y += 1;
                 self.editor.cursor.x = 0;
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Char('\t') => {
-                let tab_width = 4;
-                let target_col = (self.editor.cursor.x / tab_width + 1) * tab_width;
-                let spaces_needed = target_col - self.editor.cursor.x;
-                for _ in 0..spaces_needed {
-                    self.editor
-                        .buffer
-                        .insert_char(&(crate::buffer::Location::from(self.editor.cursor)), ' ');
-                }
-                self.editor.cursor.x = target_col;
+                let cursor = Location::from(self.editor.cursor);
+                self.editor.cursor.x = self.editor.buffer.insert_tab(&cursor);
+                self.editor.update_view();
+            }
+            Key::Ctrl(c @ ('t' | 'd')) => {
+                let y = self.editor.cursor.y;
+                let delta = self.editor.buffer.shift_indent(y, c == 't');
+                self.editor.cursor.x = self.editor.cursor.x.saturating_add_signed(delta);
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Char(c) => {
                 self.editor
@@ -123,36 +316,44 @@ impl<'a> KeyHandler<'a> {
                     .insert_char(&(crate::buffer::Location::from(self.editor.cursor)), c);
                 self.editor.cursor.x += 1;
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Backspace => {
-                self.editor.delete_under_cursor();
-                if self.editor.cursor.x == 0 && self.editor.cursor.y > 0 {
-                    self.editor.cursor.y -= 1;
-                    let prev_len = self.editor.buffer.lines[self.editor.cursor.y].grapheme_len();
-                    self.editor.cursor.x = std::cmp::min(prev_len, self.editor.cursor.x);
-                } else if self.editor.cursor.x > 0 {
-                    self.editor.cursor.x -= 1;
+                let cursor = self.editor.cursor;
+                if let Some(x) = self.editor.buffer.delete_soft_tab(&Location::from(cursor)) {
+                    self.editor.cursor.x = x;
+                    self.editor.update_view();
+                    return Ok(());
+                }
+                let joins_line = cursor.x == 0 && cursor.y > 0;
+                let prev_len = if joins_line {
+                    self.editor.buffer.lines[cursor.y - 1].grapheme_len()
+                } else {
+                    0
+                };
+                if self.editor.buffer.delete_char(&Location::from(cursor)) {
+                    if joins_line {
+                        self.editor.cursor.y -= 1;
+                        self.editor.cursor.x = prev_len;
+                    } else {
+                        self.editor.cursor.x -= 1;
+                    }
                 }
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Esc => {
                 self.editor.set_mode(Mode::Normal);
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             Key::Left | Key::Right | Key::Up | Key::Down => {
                 self.editor.handle_cursor(key)?;
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             _ => {}
         }
 
         Ok(())
     }
-    fn handle_replace(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
+    fn handle_replace(&mut self, key: Key) -> Result<()> {
         match key {
             Key::Char(c) => {
                 self.editor.delete_under_cursor();
@@ -160,38 +361,47 @@ impl<'a> KeyHandler<'a> {
                     .buffer
                     .insert_char(&(crate::buffer::Location::from(self.editor.cursor)), c);
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
                 self.editor.set_mode(Mode::Normal);
             }
             Key::Esc => {
                 self.editor.set_mode(Mode::Normal);
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             _ => {}
         }
         Ok(())
     }
-    fn handle_command(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
+    fn handle_command(&mut self, key: Key) -> Result<()> {
         match key {
-            Key::Esc => {
+            Key::Char('\n') => {
+                let cmd = std::mem::take(&mut self.editor.command_line);
                 self.editor.set_mode(Mode::Normal);
+                self.editor.set_status(String::new());
+                if let Err(e) = self.editor.execute_command(&cmd) {
+                    self.editor.set_status(e.to_string());
+                }
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
-            _ => {}
-        }
-        Ok(())
-    }
-    fn handle_visual(&mut self, key: Key, stdout: &mut std::io::Stdout) -> Result<()> {
-        match key {
+            Key::Char(c) => self.editor.command_line.push(c),
+            Key::Backspace if self.editor.command_line.is_empty() => {
+                self.editor.set_mode(Mode::Normal);
+            }
+            Key::Backspace => {
+                self.editor.command_line.pop();
+            }
             Key::Esc => {
                 self.editor.set_mode(Mode::Normal);
                 self.editor.update_view();
-                self.editor.update_cursor(stdout)?;
             }
             _ => {}
         }
         Ok(())
     }
+    fn handle_visual(&mut self, key: Key) -> Result<()> {
+        if key == Key::Esc {
+            self.editor.set_mode(Mode::Normal);
+            self.editor.update_view();
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,191 @@
+use std::io::Result;
+use std::time::Duration;
+
+use crate::command;
+use crate::input::{self, Key};
+
+// the modes mappings apply in; replace and command-line keys are never mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    Normal,
+    Visual,
+    Insert,
+}
+
+impl MapMode {
+    fn letter(self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Visual => 'v',
+            MapMode::Insert => 'i',
+        }
+    }
+}
+
+// the modes a :map style command covers, by its first letter; plain :map is
+// normal and visual like in vim
+pub fn modes(prefix: &str) -> Option<&'static [MapMode]> {
+    match prefix {
+        "" => Some(&[MapMode::Normal, MapMode::Visual]),
+        "n" => Some(&[MapMode::Normal]),
+        "v" => Some(&[MapMode::Visual]),
+        "i" => Some(&[MapMode::Insert]),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub mode: MapMode,
+    pub lhs: Vec<Key>,
+    pub rhs: Vec<Key>,
+    // the rhs runs as the builtin keys and is not mapped again
+    pub noremap: bool,
+    notation: (String, String),
+}
+
+pub enum Lookup {
+    // what was typed so far could still become a longer mapping
+    Pending,
+    // the longest mapping the typed keys start with
+    Match(Mapping),
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    maps: Vec<Mapping>,
+    // what <Leader> stands for, in key notation
+    pub leader: String,
+    // how long to wait for the rest of an ambiguous sequence, in ms
+    pub timeoutlen: usize,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            maps: Vec::new(),
+            leader: "\\".to_owned(),
+            timeoutlen: 1000,
+        }
+    }
+}
+
+impl Keymap {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeoutlen as u64)
+    }
+    pub fn map(&mut self, mode: MapMode, lhs: &str, rhs: &str, noremap: bool) -> Result<()> {
+        let lhs = expand_leader(lhs, &self.leader);
+        let rhs = expand_leader(rhs, &self.leader);
+        let keys = input::parse_keys(&lhs);
+        if keys.is_empty() {
+            return Err(command::invalid("argument required".to_owned()));
+        }
+        self.maps.retain(|m| m.mode != mode || m.lhs != keys);
+        self.maps.push(Mapping {
+            mode,
+            lhs: keys,
+            rhs: input::parse_keys(&rhs),
+            noremap,
+            notation: (lhs, rhs),
+        });
+        Ok(())
+    }
+    pub fn unmap(&mut self, mode: MapMode, lhs: &str) -> Result<()> {
+        let keys = input::parse_keys(&expand_leader(lhs, &self.leader));
+        let before = self.maps.len();
+        self.maps.retain(|m| m.mode != mode || m.lhs != keys);
+        if self.maps.len() == before {
+            return Err(command::invalid(format!("no such mapping: {}", lhs)));
+        }
+        Ok(())
+    }
+    pub fn lookup(&self, mode: MapMode, typed: &[Key], wait: bool) -> Lookup {
+        let maps = self.maps.iter().filter(|m| m.mode == mode);
+        if wait
+            && maps
+                .clone()
+                .any(|m| m.lhs.len() > typed.len() && m.lhs.starts_with(typed))
+        {
+            return Lookup::Pending;
+        }
+        maps.filter(|m| typed.starts_with(&m.lhs))
+            .max_by_key(|m| m.lhs.len())
+            .map_or(Lookup::None, |m| Lookup::Match(m.clone()))
+    }
+    // one line per mapping of those modes whose lhs starts with prefix, the
+    // way :map lists them
+    pub fn list(&self, modes: &[MapMode], prefix: &str) -> Vec<String> {
+        let prefix = input::parse_keys(&expand_leader(prefix, &self.leader));
+        self.maps
+            .iter()
+            .filter(|m| modes.contains(&m.mode) && m.lhs.starts_with(&prefix))
+            .map(|m| {
+                let star = if m.noremap { '*' } else { ' ' };
+                let (lhs, rhs) = &m.notation;
+                format!("{}  {:<12}{} {}", m.mode.letter(), lhs, star, rhs)
+            })
+            .collect()
+    }
+}
+
+// <Leader> is replaced when a mapping is defined, so changing the leader
+// later leaves existing mappings alone
+fn expand_leader(notation: &str, leader: &str) -> String {
+    let lower = notation.to_ascii_lowercase();
+    let mut out = String::new();
+    let mut rest = 0;
+    while let Some(i) = lower[rest..].find("<leader>") {
+        out.push_str(&notation[rest..rest + i]);
+        out.push_str(leader);
+        rest += i + "<leader>".len();
+    }
+    out.push_str(&notation[rest..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_waits_for_longer_mappings() {
+        let mut keymap = Keymap::default();
+        keymap.map(MapMode::Normal, "j", "gj", true).unwrap();
+        keymap.map(MapMode::Normal, "jk", "<Esc>", false).unwrap();
+        keymap
+            .map(MapMode::Insert, "<Leader>w", "<Esc>:w<CR>", false)
+            .unwrap();
+        let keys = input::parse_keys;
+        assert!(matches!(
+            keymap.lookup(MapMode::Normal, &keys("j"), true),
+            Lookup::Pending
+        ));
+        match keymap.lookup(MapMode::Normal, &keys("jx"), true) {
+            Lookup::Match(m) => assert_eq!(m.rhs, keys("gj")),
+            _ => panic!("expected j to match"),
+        }
+        assert!(matches!(
+            keymap.lookup(MapMode::Visual, &keys("j"), true),
+            Lookup::None
+        ));
+        match keymap.lookup(MapMode::Insert, &keys("\\w"), true) {
+            Lookup::Match(m) => assert_eq!(m.rhs, keys("<Esc>:w<CR>")),
+            _ => panic!("expected the leader to be expanded"),
+        }
+        assert_eq!(keymap.list(&[MapMode::Normal], "j").len(), 2);
+        assert_eq!(
+            keymap.list(&[MapMode::Normal], "j")[0],
+            "n  j           * gj"
+        );
+        keymap.unmap(MapMode::Normal, "j").unwrap();
+        assert!(keymap.unmap(MapMode::Normal, "j").is_err());
+    }
+
+    #[test]
+    fn leader_is_replaced_in_any_case() {
+        assert_eq!(expand_leader("<leader>w<LEADER>", ","), ",w,");
+        assert_eq!(expand_leader("<Space>", ","), "<Space>");
+    }
+}
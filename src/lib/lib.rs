@@ -1,11 +1,38 @@
+pub mod args;
 pub mod buffer;
+pub mod buflist;
 pub use buffer::Buffer;
 pub use buffer::Location;
+pub mod command;
+pub use command::Command;
 pub mod editor;
 pub use editor::Editor;
 pub mod view;
 pub use view::View;
+pub mod widget;
+pub mod window;
+pub use widget::EditorView;
 pub mod cursor;
 pub use cursor::Cursor;
+pub mod input;
+pub use input::Key;
 pub mod keyhandler;
+pub mod keymap;
 pub use keyhandler::KeyHandler;
+pub mod encoding;
+pub mod file;
+pub mod gutter;
+pub use encoding::Encoding;
+#[cfg(any(test, feature = "harness"))]
+pub mod harness;
+pub mod hex;
+pub mod options;
+pub use hex::HexEditor;
+pub mod diff;
+pub mod display;
+pub mod event;
+pub mod swap;
+pub mod syntax;
+pub mod terminal;
+pub mod theme;
+pub use event::{Event, EventLoop};
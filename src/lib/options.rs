@@ -0,0 +1,571 @@
+use std::fmt;
+use std::io::Result;
+
+use crate::buffer::LineEnding;
+use crate::command;
+use crate::display::ListChars;
+use crate::editor::Editor;
+use crate::encoding::Encoding;
+use crate::syntax::{self, Highlighter};
+use crate::theme::{ColorDepth, Theme};
+
+// where an option's value lives: one for the editor, one per buffer or one
+// per window. a :set of a buffer or window option also becomes the value
+// new buffers and windows start with, like vim's global value of a local one.
+// file options are per buffer too but describe the file it was read from, so
+// :set never passes them on, as if it were :setlocal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Buffer,
+    Window,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bool,
+    // with the smallest value allowed
+    Number(isize),
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(isize),
+    Text(String),
+}
+
+impl Value {
+    fn flag(&self) -> bool {
+        matches!(self, Value::Bool(true))
+    }
+    fn number(&self) -> isize {
+        match self {
+            Value::Number(n) => *n,
+            _ => 0,
+        }
+    }
+    fn text(&self) -> &str {
+        match self {
+            Value::Text(s) => s,
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OptionDef {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub scope: Scope,
+    pub kind: Kind,
+    pub default: &'static str,
+    get: fn(&Editor) -> Value,
+    set: fn(&mut Editor, &Value) -> Result<()>,
+}
+
+impl OptionDef {
+    pub fn parse(&self, value: &str) -> Result<Value> {
+        let invalid = || command::invalid(format!("invalid {}: {}", self.name, value));
+        match self.kind {
+            Kind::Bool => match value {
+                "true" | "on" => Ok(Value::Bool(true)),
+                "false" | "off" => Ok(Value::Bool(false)),
+                _ => Err(invalid()),
+            },
+            Kind::Number(min) => value
+                .parse()
+                .ok()
+                .filter(|&n| n >= min)
+                .map(Value::Number)
+                .ok_or_else(invalid),
+            Kind::Text => Ok(Value::Text(value.to_owned())),
+        }
+    }
+    pub fn get(&self, editor: &Editor) -> Value {
+        (self.get)(editor)
+    }
+    pub fn set(&self, editor: &mut Editor, value: &Value) -> Result<()> {
+        (self.set)(editor, value)
+    }
+}
+
+pub const OPTIONS: &[OptionDef] = &[
+    OptionDef {
+        name: "autoread",
+        short: "ar",
+        scope: Scope::Global,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.autoread),
+        set: |e, v| {
+            e.autoread = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "autosave",
+        short: "",
+        scope: Scope::Global,
+        kind: Kind::Number(0),
+        default: "0",
+        get: |e| Value::Number(e.autosave as isize),
+        set: |e, v| {
+            e.autosave = v.number() as usize;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "backup",
+        short: "bk",
+        scope: Scope::Global,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.backup),
+        set: |e, v| {
+            e.backup = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "ignorecase",
+        short: "ic",
+        scope: Scope::Global,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.ignorecase),
+        set: |e, v| {
+            e.ignorecase = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "theme",
+        short: "",
+        scope: Scope::Global,
+        kind: Kind::Text,
+        default: "default",
+        get: |e| Value::Text(e.theme.name.clone()),
+        set: |e, v| {
+            e.theme = Theme::load(v.text())?.degrade(ColorDepth::detect());
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "mapleader",
+        short: "",
+        scope: Scope::Global,
+        kind: Kind::Text,
+        default: "\\",
+        get: |e| Value::Text(e.keymap.leader.clone()),
+        set: |e, v| {
+            e.keymap.leader = v.text().to_owned();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "timeoutlen",
+        short: "tm",
+        scope: Scope::Global,
+        kind: Kind::Number(0),
+        default: "1000",
+        get: |e| Value::Number(e.keymap.timeoutlen as isize),
+        set: |e, v| {
+            e.keymap.timeoutlen = v.number() as usize;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "endofline",
+        short: "eol",
+        scope: Scope::File,
+        kind: Kind::Bool,
+        default: "true",
+        get: |e| Value::Bool(e.buffer.final_newline),
+        set: |e, v| {
+            e.buffer.final_newline = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "bomb",
+        short: "",
+        scope: Scope::File,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.buffer.bom),
+        set: |e, v| {
+            e.buffer.bom = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "fileformat",
+        short: "ff",
+        scope: Scope::File,
+        kind: Kind::Text,
+        default: "unix",
+        get: |e| Value::Text(e.buffer.line_ending.name().to_owned()),
+        set: |e, v| {
+            e.buffer.line_ending = LineEnding::from_name(v.text())
+                .ok_or_else(|| command::invalid(format!("invalid fileformat: {}", v)))?;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "fileencoding",
+        short: "fenc",
+        scope: Scope::File,
+        kind: Kind::Text,
+        default: "utf-8",
+        get: |e| Value::Text(e.buffer.encoding.name().to_owned()),
+        set: |e, v| {
+            let encoding = Encoding::from_name(v.text())
+                .ok_or_else(|| command::invalid(format!("invalid fileencoding: {}", v)))?;
+            e.buffer.bom = e.buffer.bom && !encoding.bom().is_empty();
+            e.buffer.encoding = encoding;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "tabstop",
+        short: "ts",
+        scope: Scope::Buffer,
+        kind: Kind::Number(1),
+        default: "8",
+        get: |e| Value::Number(e.buffer.tabstop as isize),
+        set: |e, v| {
+            e.buffer.tabstop = v.number() as usize;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "shiftwidth",
+        short: "sw",
+        scope: Scope::Buffer,
+        kind: Kind::Number(0),
+        default: "4",
+        get: |e| Value::Number(e.buffer.shiftwidth as isize),
+        set: |e, v| {
+            e.buffer.shiftwidth = v.number() as usize;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "softtabstop",
+        short: "sts",
+        scope: Scope::Buffer,
+        kind: Kind::Number(-1),
+        default: "-1",
+        get: |e| Value::Number(e.buffer.softtabstop),
+        set: |e, v| {
+            e.buffer.softtabstop = v.number();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "expandtab",
+        short: "et",
+        scope: Scope::Buffer,
+        kind: Kind::Bool,
+        default: "true",
+        get: |e| Value::Bool(e.buffer.expandtab),
+        set: |e, v| {
+            e.buffer.expandtab = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "readonly",
+        short: "ro",
+        scope: Scope::File,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.buffer.readonly),
+        set: |e, v| {
+            e.buffer.readonly = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "syntax",
+        short: "syn",
+        scope: Scope::Buffer,
+        kind: Kind::Text,
+        default: "off",
+        get: |e| Value::Text(e.syntax.name().to_owned()),
+        set: |e, v| {
+            let grammar = match v.text() {
+                "off" | "" => None,
+                name => Some(
+                    syntax::by_name(name)?
+                        .ok_or_else(|| command::invalid(format!("unknown syntax: {}", name)))?,
+                ),
+            };
+            e.syntax = Highlighter::new(grammar);
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "list",
+        short: "",
+        scope: Scope::Window,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.view.list),
+        set: |e, v| {
+            e.view.list = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "listchars",
+        short: "lcs",
+        scope: Scope::Window,
+        kind: Kind::Text,
+        default: "tab:> ,trail:-,nbsp:+,eol:$",
+        get: |e| Value::Text(e.view.listchars.to_string()),
+        set: |e, v| {
+            e.view.listchars = ListChars::parse(v.text())
+                .ok_or_else(|| command::invalid(format!("invalid listchars: {}", v)))?;
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "wrap",
+        short: "",
+        scope: Scope::Window,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.view.wrap),
+        set: |e, v| {
+            e.view.wrap = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "showbreak",
+        short: "sbr",
+        scope: Scope::Window,
+        kind: Kind::Text,
+        default: "",
+        get: |e| Value::Text(e.view.showbreak.clone()),
+        set: |e, v| {
+            e.view.showbreak = v.text().to_owned();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "breakindent",
+        short: "bri",
+        scope: Scope::Window,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.view.breakindent),
+        set: |e, v| {
+            e.view.breakindent = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "number",
+        short: "nu",
+        scope: Scope::Window,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.view.number),
+        set: |e, v| {
+            e.view.number = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "relativenumber",
+        short: "rnu",
+        scope: Scope::Window,
+        kind: Kind::Bool,
+        default: "false",
+        get: |e| Value::Bool(e.view.relativenumber),
+        set: |e, v| {
+            e.view.relativenumber = v.flag();
+            Ok(())
+        },
+    },
+    OptionDef {
+        name: "scrolloff",
+        short: "so",
+        scope: Scope::Window,
+        kind: Kind::Number(0),
+        default: "0",
+        get: |e| Value::Number(e.view.scrolloff as isize),
+        set: |e, v| {
+            e.view.scrolloff = v.number() as usize;
+            Ok(())
+        },
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|o| o.name == name || (!o.short.is_empty() && o.short == name))
+}
+
+fn unknown(name: &str) -> std::io::Error {
+    command::invalid(format!("unknown option: {}", name))
+}
+
+// one :set argument: opt, noopt, invopt, opt!, opt?, opt& or opt=value. a
+// plain non-boolean opt shows the value like vim does
+pub fn apply(editor: &mut Editor, arg: &str) -> Result<()> {
+    if let Some((name, value)) = arg.split_once('=') {
+        let def = find(name).ok_or_else(|| unknown(name))?;
+        let value = def.parse(value)?;
+        return assign(editor, def, value);
+    }
+    if let Some(name) = arg.strip_suffix('&') {
+        let def = find(name).ok_or_else(|| unknown(name))?;
+        let value = def.parse(def.default)?;
+        return assign(editor, def, value);
+    }
+    if let Some(name) = arg.strip_suffix('?') {
+        let def = find(name).ok_or_else(|| unknown(name))?;
+        show(editor, name, def);
+        return Ok(());
+    }
+    let toggle = arg
+        .strip_suffix('!')
+        .or_else(|| arg.strip_prefix("inv"))
+        .and_then(find);
+    if let Some(def) = toggle.filter(|d| d.kind == Kind::Bool) {
+        let value = Value::Bool(!def.get(editor).flag());
+        return assign(editor, def, value);
+    }
+    if let Some(def) = find(arg) {
+        if def.kind == Kind::Bool {
+            return assign(editor, def, Value::Bool(true));
+        }
+        show(editor, arg, def);
+        return Ok(());
+    }
+    match arg.strip_prefix("no").and_then(find) {
+        Some(def) if def.kind == Kind::Bool => assign(editor, def, Value::Bool(false)),
+        _ => Err(unknown(arg)),
+    }
+}
+
+fn show(editor: &mut Editor, name: &str, def: &OptionDef) {
+    let status = match def.get(editor) {
+        Value::Bool(true) => name.to_owned(),
+        Value::Bool(false) => format!("no{}", name),
+        value => format!("{}={}", name, value),
+    };
+    editor.set_status(status);
+}
+
+fn assign(editor: &mut Editor, def: &'static OptionDef, value: Value) -> Result<()> {
+    def.set(editor, &value)?;
+    if matches!(def.scope, Scope::Buffer | Scope::Window) {
+        pass_on(editor, def, value);
+    }
+    editor.update_view();
+    Ok(())
+}
+
+fn pass_on(editor: &mut Editor, def: &'static OptionDef, value: Value) {
+    editor.inherited.retain(|(d, _)| d.name != def.name);
+    editor.inherited.push((def, value));
+}
+
+// a value every buffer opened from now on starts with, even for a file
+// option; -R makes every buffer read-only this way
+pub fn set_for_all(editor: &mut Editor, name: &str, value: Value) -> Result<()> {
+    let def = find(name).ok_or_else(|| unknown(name))?;
+    def.set(editor, &value)?;
+    pass_on(editor, def, value);
+    Ok(())
+}
+
+// gives a fresh buffer or window the values :set chose for its scope
+pub fn inherit(editor: &mut Editor, scope: Scope) {
+    let values = editor
+        .inherited
+        .iter()
+        .filter(|(d, _)| d.scope == scope || (scope == Scope::Buffer && d.scope == Scope::File))
+        .cloned()
+        .collect::<Vec<_>>();
+    for (def, value) in values {
+        let _ = def.set(editor, &value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+
+    #[test]
+    fn defaults_match_a_new_editor() {
+        let editor = Editor::default();
+        for def in OPTIONS {
+            assert_eq!(
+                def.parse(def.default).unwrap(),
+                def.get(&editor),
+                "{}",
+                def.name
+            );
+        }
+    }
+
+    #[test]
+    fn set_forms() {
+        let mut editor = Editor::default();
+        apply(&mut editor, "ts=4").unwrap();
+        apply(&mut editor, "nu").unwrap();
+        apply(&mut editor, "noet").unwrap();
+        apply(&mut editor, "list!").unwrap();
+        assert_eq!(editor.buffer.tabstop, 4);
+        assert!(editor.view.number && editor.view.list && !editor.buffer.expandtab);
+        apply(&mut editor, "ts&").unwrap();
+        assert_eq!(editor.buffer.tabstop, 8);
+        apply(&mut editor, "et?").unwrap();
+        assert_eq!(editor.status(), "noet");
+        apply(&mut editor, "sw").unwrap();
+        assert_eq!(editor.status(), "sw=4");
+        let err = apply(&mut editor, "ts=0").unwrap_err();
+        assert_eq!(err.to_string(), "invalid tabstop: 0");
+        assert!(apply(&mut editor, "nots").is_err());
+        assert!(apply(&mut editor, "bogus").is_err());
+    }
+
+    #[test]
+    fn file_options_stay_with_their_buffer() {
+        let mut editor = Editor::default();
+        apply(&mut editor, "ts=4").unwrap();
+        for arg in ["ff=dos", "fenc=latin1", "noeol", "ro"] {
+            apply(&mut editor, arg).unwrap();
+        }
+        editor.buffer = Buffer::default();
+        inherit(&mut editor, Scope::Buffer);
+        assert_eq!(editor.buffer.tabstop, 4);
+        assert_eq!(editor.buffer.line_ending, LineEnding::Lf);
+        assert_eq!(editor.buffer.encoding, Encoding::Utf8);
+        assert!(editor.buffer.final_newline && !editor.buffer.readonly);
+        set_for_all(&mut editor, "readonly", Value::Bool(true)).unwrap();
+        editor.buffer = Buffer::default();
+        inherit(&mut editor, Scope::Buffer);
+        assert!(editor.buffer.readonly);
+    }
+}
@@ -0,0 +1,252 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::file;
+
+const MAGIC: &str = "ve-swap 1";
+pub const UPDATE_COUNT: usize = 200;
+pub const UPDATE_TIME: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapInfo {
+    pub pid: u32,
+    pub body: Option<Vec<u8>>,
+}
+
+impl SwapInfo {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let bad = || Error::new(ErrorKind::InvalidData, "not a ve swap file");
+        let mut rest = data;
+        let mut header = Vec::new();
+        loop {
+            let end = rest.iter().position(|&b| b == b'\n').ok_or_else(bad)?;
+            let line = std::str::from_utf8(&rest[..end]).map_err(|_| bad())?;
+            rest = &rest[end + 1..];
+            if line.is_empty() {
+                break;
+            }
+            header.push(line);
+        }
+        if header.first() != Some(&MAGIC) {
+            return Err(bad());
+        }
+        let mut info = SwapInfo { pid: 0, body: None };
+        for line in &header[1..] {
+            match line.split_once(' ') {
+                Some(("pid", pid)) => info.pid = pid.parse().map_err(|_| bad())?,
+                Some(("modified", "1")) => info.body = Some(rest.to_vec()),
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let modified = self.body.is_some() as u8;
+        let mut out =
+            format!("{}\npid {}\nmodified {}\n\n", MAGIC, self.pid, modified).into_bytes();
+        if let Some(body) = &self.body {
+            out.extend_from_slice(body);
+        }
+        out
+    }
+    // signal 0 only checks that the process exists; EPERM means it does but
+    // belongs to someone else
+    pub fn process_alive(&self) -> bool {
+        if self.pid == std::process::id() {
+            return false;
+        }
+        let Ok(pid) = libc::pid_t::try_from(self.pid) else {
+            return false;
+        };
+        if pid <= 0 {
+            return false;
+        }
+        let found = unsafe { libc::kill(pid, 0) } == 0;
+        found || Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+fn local_swap_path(path: &Path) -> PathBuf {
+    let target = file::resolve_symlinks(path);
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".swp");
+    target.with_file_name(name)
+}
+
+fn state_dir() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+    Some(state.join("ve"))
+}
+
+// same idea as vim's directory option: the full path with / replaced by %
+fn state_swap_path(path: &Path) -> Option<PathBuf> {
+    let state = state_dir()?;
+    let target = file::resolve_symlinks(path);
+    let absolute = env::current_dir().ok()?.join(target);
+    let name = absolute.to_string_lossy().replace('/', "%");
+    Some(state.join("swap").join(format!("{}.swp", name)))
+}
+
+// for buffers that have no swap file, e.g. ones that were never named
+pub fn write_recovery(name: &str, contents: &[u8]) -> Result<PathBuf> {
+    let dir = state_dir().unwrap_or_else(env::temp_dir).join("recovery");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.{}", name, std::process::id()));
+    file::write_private(&path, contents)?;
+    Ok(path)
+}
+
+// an unsaved buffer, ready to be written out by the panic hook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dump {
+    pub swap: Option<PathBuf>,
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+// the unsaved buffers as of the last event the editor finished handling.
+// the panic hook writes these rather than touch an editor that was half way
+// through a change, which also covers panics on other threads and
+// panic = "abort" builds; edits made by the event that panicked are lost.
+// hidden buffers are kept apart so typing only re-encodes the current one
+struct Snapshot {
+    current: Option<Dump>,
+    hidden: Vec<Dump>,
+}
+
+static SNAPSHOT: Mutex<Snapshot> = Mutex::new(Snapshot {
+    current: None,
+    hidden: Vec::new(),
+});
+
+pub fn set_snapshot(current: Option<Dump>) {
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        snapshot.current = current;
+    }
+}
+
+pub fn set_hidden_snapshot(hidden: Vec<Dump>) {
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        snapshot.hidden = hidden;
+    }
+}
+
+// writes every buffer in the snapshot to its swap file, or to a recovery
+// file when it has none, and returns where they went
+pub fn dump_snapshot() -> Vec<PathBuf> {
+    // try_lock: the panic may have happened while the snapshot was being set
+    let Ok(snapshot) = SNAPSHOT.try_lock() else {
+        return Vec::new();
+    };
+    snapshot
+        .current
+        .iter()
+        .chain(&snapshot.hidden)
+        .filter_map(|dump| match &dump.swap {
+            Some(path) => Swap::adopt(path.clone())
+                .write(Some(&dump.contents))
+                .ok()
+                .map(|_| path.clone()),
+            None => write_recovery(&dump.name, &dump.contents).ok(),
+        })
+        .collect()
+}
+
+fn candidates(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![local_swap_path(path)];
+    paths.extend(state_swap_path(path));
+    paths
+}
+
+pub fn find(path: &Path) -> Option<(PathBuf, SwapInfo)> {
+    candidates(path).into_iter().find_map(|swap| {
+        let info = SwapInfo::parse(&fs::read(&swap).ok()?).ok()?;
+        Some((swap, info))
+    })
+}
+
+#[derive(Debug)]
+pub struct Swap {
+    path: PathBuf,
+}
+
+impl Swap {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut last_err = Error::new(ErrorKind::NotFound, "no swap location");
+        for candidate in candidates(path) {
+            let swap = Swap { path: candidate };
+            match swap.write(None) {
+                Ok(()) => return Ok(swap),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+    pub fn adopt(path: PathBuf) -> Self {
+        Swap { path }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn write(&self, body: Option<&[u8]>) -> Result<()> {
+        if let Some(dir) = self.path.parent()
+            && !dir.exists()
+        {
+            fs::create_dir_all(dir)?;
+        }
+        let info = SwapInfo {
+            pid: std::process::id(),
+            body: body.map(<[u8]>::to_vec),
+        };
+        file::write_private(&self.path, &info.to_bytes())
+    }
+    pub fn remove(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn swap_info_round_trips() {
+        let info = SwapInfo {
+            pid: 42,
+            body: Some(b"line\n\nwith blank\n".to_vec()),
+        };
+        assert_eq!(SwapInfo::parse(&info.to_bytes()).unwrap(), info);
+        let clean = SwapInfo { pid: 7, body: None };
+        assert_eq!(SwapInfo::parse(&clean.to_bytes()).unwrap(), clean);
+        assert!(SwapInfo::parse(b"random file\n\n").is_err());
+    }
+
+    #[test]
+    fn swap_lives_next_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("ve_swap_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        let swap = Swap::create(&path).unwrap();
+        assert_eq!(swap.path(), dir.join(".notes.txt.swp"));
+        swap.write(Some(b"unsaved")).unwrap();
+        let (found, info) = find(&path).unwrap();
+        assert_eq!(found, dir.join(".notes.txt.swp"));
+        assert_eq!(info.body.as_deref(), Some(&b"unsaved"[..]));
+        assert!(!info.process_alive());
+        // init is always there, even when it is not ours to signal
+        assert!(SwapInfo { pid: 1, body: None }.process_alive());
+        let mode = fs::metadata(&found).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        swap.remove();
+        assert!(find(&path).is_none());
+    }
+}
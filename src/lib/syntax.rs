@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::buffer::Buffer;
+use crate::file;
+
+// the grammars that ship with ve; files in $XDG_CONFIG_HOME/ve/syntax with
+// the same name or extension take precedence
+const BUILTIN: &[&str] = &[
+    include_str!("../../syntax/rust.syn"),
+    include_str!("../../syntax/toml.syn"),
+    include_str!("../../syntax/markdown.syn"),
+    include_str!("../../syntax/sh.syn"),
+    include_str!("../../syntax/json.syn"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Type,
+    Constant,
+    Title,
+    Special,
+}
+
+impl Group {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let group = match name {
+            "comment" => Group::Comment,
+            "string" => Group::String,
+            "number" => Group::Number,
+            "keyword" => Group::Keyword,
+            "type" => Group::Type,
+            "constant" => Group::Constant,
+            "title" => Group::Title,
+            "special" => Group::Special,
+            _ => return None,
+        };
+        Some(group)
+    }
+    // the theme group it is drawn with
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Comment => "Comment",
+            Group::String => "String",
+            Group::Number => "Number",
+            Group::Keyword => "Keyword",
+            Group::Type => "Type",
+            Group::Constant => "Constant",
+            Group::Title => "Title",
+            Group::Special => "Special",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    // from start to the end of the line; bol only at the start of the line
+    // or after indentation, blank only there or after whitespace
+    Line {
+        group: Group,
+        start: String,
+        bol: bool,
+        blank: bool,
+    },
+    // from start to end, possibly over several lines
+    Region {
+        group: Group,
+        start: String,
+        end: String,
+        escape: Option<char>,
+        nested: bool,
+    },
+    // a quoted character: one char, or the escape and what follows it up to
+    // the closing quote, e.g. 'a' or '\u{1F600}'
+    Char {
+        group: Group,
+        quote: char,
+        escape: Option<char>,
+    },
+    // start and the word right after it, e.g. a lifetime 'a
+    Prefix {
+        group: Group,
+        start: String,
+    },
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the length of the quoted character at the start of rest
+fn char_len(rest: &str, quote: char, escape: Option<char>) -> Option<usize> {
+    let inner = rest.strip_prefix(quote)?;
+    let first = inner.chars().next()?;
+    let body = if Some(first) == escape {
+        // the escaped char may be the quote itself, as in '\''
+        let escaped = inner[first.len_utf8()..].chars().next()?;
+        let after = first.len_utf8() + escaped.len_utf8();
+        after + inner[after..].find(quote)?
+    } else {
+        first.len_utf8()
+    };
+    inner[body..]
+        .starts_with(quote)
+        .then(|| body + 2 * quote.len_utf8())
+}
+
+// a language in the .syn format: one directive per line, `#` at the start of
+// a line is a comment.
+//   name rust / extensions rs / names Cargo.lock
+//   line GROUP START [bol] [blank]
+//   region GROUP START END [nested] [escape=C]
+//   char GROUP QUOTE [escape=C]
+//   prefix GROUP START
+//   numbers
+//   keyword|type|constant WORDS...
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grammar {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub names: Vec<String>,
+    pub rules: Vec<Rule>,
+    pub words: HashMap<String, Group>,
+    pub numbers: bool,
+}
+
+fn grammar_error(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+impl Grammar {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut grammar = Grammar::default();
+        for (i, line) in source.lines().enumerate() {
+            let n = i + 1;
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+            let args = words.collect::<Vec<_>>();
+            let group = |name: Option<&&str>| {
+                name.and_then(|g| Group::from_name(g))
+                    .ok_or_else(|| grammar_error(n, "expected a group name"))
+            };
+            match directive {
+                d if d.starts_with('#') => {}
+                "name" => grammar.name = args.join(" "),
+                "extensions" => grammar
+                    .extensions
+                    .extend(args.iter().map(|s| s.to_string())),
+                "names" => grammar.names.extend(args.iter().map(|s| s.to_string())),
+                "numbers" => grammar.numbers = true,
+                "keyword" | "type" | "constant" => {
+                    let group = group(Some(&directive))?;
+                    grammar
+                        .words
+                        .extend(args.iter().map(|w| (w.to_string(), group)));
+                }
+                "line" => {
+                    let start = args
+                        .get(1)
+                        .ok_or_else(|| grammar_error(n, "line needs a start"))?;
+                    let flags = &args[2..];
+                    if let Some(flag) = flags.iter().find(|f| !matches!(**f, "bol" | "blank")) {
+                        return Err(grammar_error(n, &format!("unknown flag {}", flag)));
+                    }
+                    grammar.rules.push(Rule::Line {
+                        group: group(args.first())?,
+                        start: start.to_string(),
+                        bol: flags.contains(&"bol"),
+                        blank: flags.contains(&"blank"),
+                    });
+                }
+                "region" => {
+                    let (Some(start), Some(end)) = (args.get(1), args.get(2)) else {
+                        return Err(grammar_error(n, "region needs a start and an end"));
+                    };
+                    let mut nested = false;
+                    let mut escape = None;
+                    for flag in &args[3..] {
+                        match flag.strip_prefix("escape=") {
+                            Some(c) if c.chars().count() == 1 => escape = c.chars().next(),
+                            None if *flag == "nested" => nested = true,
+                            _ => return Err(grammar_error(n, &format!("unknown flag {}", flag))),
+                        }
+                    }
+                    grammar.rules.push(Rule::Region {
+                        group: group(args.first())?,
+                        start: start.to_string(),
+                        end: end.to_string(),
+                        escape,
+                        nested,
+                    });
+                }
+                "char" => {
+                    let quote = args
+                        .get(1)
+                        .and_then(|q| q.chars().next())
+                        .ok_or_else(|| grammar_error(n, "char needs a quote"))?;
+                    let mut escape = None;
+                    for flag in &args[2..] {
+                        match flag.strip_prefix("escape=") {
+                            Some(c) if c.chars().count() == 1 => escape = c.chars().next(),
+                            _ => return Err(grammar_error(n, &format!("unknown flag {}", flag))),
+                        }
+                    }
+                    grammar.rules.push(Rule::Char {
+                        group: group(args.first())?,
+                        quote,
+                        escape,
+                    });
+                }
+                "prefix" => {
+                    let start = args
+                        .get(1)
+                        .ok_or_else(|| grammar_error(n, "prefix needs a start"))?;
+                    grammar.rules.push(Rule::Prefix {
+                        group: group(args.first())?,
+                        start: start.to_string(),
+                    });
+                }
+                other => return Err(grammar_error(n, &format!("unknown directive {}", other))),
+            }
+        }
+        if grammar.name.is_empty() {
+            return Err(grammar_error(1, "missing name"));
+        }
+        Ok(grammar)
+    }
+    fn matches(&self, path: &Path) -> bool {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        self.names.iter().any(|n| n == name) || self.extensions.iter().any(|e| e == extension)
+    }
+    // the lexer state at the end of line, given the one at its start, with
+    // the highlighted byte ranges of the line
+    pub fn tokenize(&self, line: &str, state: State) -> (Vec<Span>, State) {
+        let mut spans = Vec::new();
+        let mut state = state;
+        let mut i = 0;
+        if state.region.is_some() {
+            i = self.scan_region(line, 0, &mut state, &mut spans);
+        }
+        while i < line.len() {
+            let rest = &line[i..];
+            let before = line[..i].chars().next_back();
+            if let Some((n, rule)) = self.rules.iter().enumerate().find(|(_, rule)| match rule {
+                Rule::Line {
+                    start, bol, blank, ..
+                } => {
+                    rest.starts_with(start.as_str())
+                        && (!bol || line[..i].trim().is_empty())
+                        && (!blank || before.is_none_or(char::is_whitespace))
+                }
+                Rule::Region { start, .. } => rest.starts_with(start.as_str()),
+                Rule::Char { quote, escape, .. } => char_len(rest, *quote, *escape).is_some(),
+                Rule::Prefix { start, .. } => {
+                    rest.strip_prefix(start.as_str())
+                        .and_then(|r| r.chars().next())
+                        .is_some_and(is_word)
+                        && !before.is_some_and(is_word)
+                }
+            }) {
+                match rule {
+                    Rule::Line { group, .. } => {
+                        spans.push(Span::new(i, line.len(), *group));
+                        break;
+                    }
+                    Rule::Region { start, .. } => {
+                        state = State {
+                            region: Some(n),
+                            depth: 0,
+                        };
+                        let from = i;
+                        i = self.scan_region(line, i + start.len(), &mut state, &mut spans);
+                        if let Some(span) = spans.last_mut() {
+                            span.start = from;
+                        }
+                        continue;
+                    }
+                    Rule::Char {
+                        group,
+                        quote,
+                        escape,
+                    } => {
+                        let len = char_len(rest, *quote, *escape).unwrap_or(rest.len());
+                        spans.push(Span::new(i, i + len, *group));
+                        i += len;
+                        continue;
+                    }
+                    Rule::Prefix { group, start } => {
+                        let word = &rest[start.len()..];
+                        let len = start.len() + word.find(|c| !is_word(c)).unwrap_or(word.len());
+                        spans.push(Span::new(i, i + len, *group));
+                        i += len;
+                        continue;
+                    }
+                }
+            }
+            let c = rest.chars().next().unwrap_or_default();
+            let word_len = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            if word_len == 0 {
+                i += c.len_utf8();
+                continue;
+            }
+            if c.is_ascii_digit() && self.numbers {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len());
+                spans.push(Span::new(i, i + len, Group::Number));
+                i += len;
+                continue;
+            }
+            if let Some(&group) = self.words.get(&rest[..word_len]) {
+                spans.push(Span::new(i, i + word_len, group));
+            }
+            i += word_len;
+        }
+        (spans, state)
+    }
+    // consumes the inside of the region in state from i, up to and including
+    // its end, and pushes it as one span; returns where scanning goes on
+    fn scan_region(&self, line: &str, i: usize, state: &mut State, spans: &mut Vec<Span>) -> usize {
+        let Some(Rule::Region {
+            group,
+            start,
+            end,
+            escape,
+            nested,
+        }) = state.region.and_then(|n| self.rules.get(n))
+        else {
+            state.region = None;
+            return i;
+        };
+        let from = i;
+        let mut i = i;
+        while i < line.len() {
+            let rest = &line[i..];
+            let c = rest.chars().next().unwrap_or_default();
+            if Some(c) == *escape {
+                i += c.len_utf8();
+                i += line[i..].chars().next().map_or(0, char::len_utf8);
+            } else if *nested && rest.starts_with(start.as_str()) {
+                state.depth += 1;
+                i += start.len();
+            } else if rest.starts_with(end.as_str()) {
+                i += end.len();
+                if state.depth == 0 {
+                    *state = State::default();
+                    break;
+                }
+                state.depth -= 1;
+            } else {
+                i += c.len_utf8();
+            }
+        }
+        spans.push(Span::new(from, i, *group));
+        i
+    }
+}
+
+// the grammar for a file: user grammars first, then the builtin ones. a user
+// grammar that fails to parse is reported rather than skipped silently
+pub fn for_path(path: &Path) -> Result<Option<Grammar>> {
+    for grammar in user_grammars()? {
+        if grammar.matches(path) {
+            return Ok(Some(grammar));
+        }
+    }
+    Ok(builtin().into_iter().find(|g| g.matches(path)))
+}
+
+pub fn by_name(name: &str) -> Result<Option<Grammar>> {
+    let mut all = user_grammars()?;
+    all.extend(builtin());
+    Ok(all.into_iter().find(|g| g.name == name))
+}
+
+fn builtin() -> Vec<Grammar> {
+    BUILTIN
+        .iter()
+        .filter_map(|s| Grammar::parse(s).ok())
+        .collect()
+}
+
+fn user_grammars() -> Result<Vec<Grammar>> {
+    let Some(dir) = file::config_dir().map(|d| d.join("syntax")) else {
+        return Ok(Vec::new());
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "syn"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            Grammar::parse(&fs::read_to_string(path)?)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+        })
+        .collect()
+}
+
+// lexer state between lines: the region still open and how deeply it nests
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    region: Option<usize>,
+    depth: usize,
+}
+
+// a highlighted byte range of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub group: Group,
+}
+
+impl Span {
+    fn new(start: usize, end: usize, group: Group) -> Self {
+        Self { start, end, group }
+    }
+}
+
+// spans and end state of every line from the top down to the last one drawn;
+// an edit drops the cache from the changed line on, so only that line and
+// the ones after it are tokenized again
+#[derive(Debug, Default)]
+pub struct Highlighter {
+    grammar: Option<Grammar>,
+    lines: Vec<(Vec<Span>, State)>,
+}
+
+impl Highlighter {
+    pub fn new(grammar: Option<Grammar>) -> Self {
+        Self {
+            grammar,
+            lines: Vec::new(),
+        }
+    }
+    pub fn name(&self) -> &str {
+        self.grammar.as_ref().map_or("off", |g| g.name.as_str())
+    }
+    pub fn invalidate(&mut self, y: usize) {
+        self.lines.truncate(y);
+    }
+    pub fn cached(&self) -> usize {
+        self.lines.len()
+    }
+    // tokenizes up to line end (exclusive)
+    pub fn update(&mut self, buffer: &Buffer, end: usize) {
+        let Some(grammar) = &self.grammar else {
+            return;
+        };
+        let end = end.min(buffer.line_count());
+        while self.lines.len() < end {
+            let state = self.lines.last().map_or(State::default(), |l| l.1);
+            let line = buffer.line_at(self.lines.len());
+            self.lines.push(grammar.tokenize(line, state));
+        }
+    }
+    pub fn spans(&self, y: usize) -> &[Span] {
+        self.lines.get(y).map_or(&[], |l| l.0.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups<'a>(
+        grammar: &Grammar,
+        line: &'a str,
+        state: State,
+    ) -> (Vec<(&'a str, Group)>, State) {
+        let (spans, state) = grammar.tokenize(line, state);
+        let out = spans
+            .iter()
+            .map(|s| (&line[s.start..s.end], s.group))
+            .collect();
+        (out, state)
+    }
+
+    #[test]
+    fn builtin_grammars_parse() {
+        let names = BUILTIN
+            .iter()
+            .map(|s| Grammar::parse(s).unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["rust", "toml", "markdown", "sh", "json"]);
+        let toml = builtin()
+            .into_iter()
+            .find(|g| g.matches(Path::new("Cargo.lock")));
+        assert_eq!(toml.map(|g| g.name), Some("toml".to_owned()));
+        let err = Grammar::parse("name x\nregion string \"\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: region needs a start and an end");
+    }
+
+    #[test]
+    fn tokenizes_rust_with_nested_comments_across_lines() {
+        let rust = by_name("rust").unwrap().unwrap();
+        let (spans, state) = groups(&rust, "let s = \"a\\\"b\"; /* x /* y */", State::default());
+        assert_eq!(
+            spans,
+            vec![
+                ("let", Group::Keyword),
+                ("\"a\\\"b\"", Group::String),
+                ("/* x /* y */", Group::Comment),
+            ]
+        );
+        let (spans, state) = groups(&rust, "still */ 42u8", state);
+        assert_eq!(
+            spans,
+            vec![("still */", Group::Comment), ("42u8", Group::Number)]
+        );
+        assert_eq!(state, State::default());
+        let sh = by_name("sh").unwrap().unwrap();
+        let (spans, _) = groups(&sh, "echo $# # done", State::default());
+        assert_eq!(spans, vec![("# done", Group::Comment)]);
+    }
+
+    #[test]
+    fn rust_char_literals_and_lifetimes_are_not_strings() {
+        let rust = by_name("rust").unwrap().unwrap();
+        let line = r#"fn f<'a>(s: &'a str) -> char { if s == "x" { '"' } else { '\'' } }"#;
+        let (spans, state) = groups(&rust, line, State::default());
+        assert_eq!(
+            spans,
+            vec![
+                ("fn", Group::Keyword),
+                ("'a", Group::Special),
+                ("'a", Group::Special),
+                ("str", Group::Type),
+                ("char", Group::Type),
+                ("if", Group::Keyword),
+                ("\"x\"", Group::String),
+                ("'\"'", Group::String),
+                ("else", Group::Keyword),
+                ("'\\''", Group::String),
+            ]
+        );
+        assert_eq!(state, State::default());
+        let (spans, _) = groups(&rust, r"'\u{1F600}' 'static", State::default());
+        assert_eq!(
+            spans,
+            vec![("'\\u{1F600}'", Group::String), ("'static", Group::Special)]
+        );
+    }
+
+    #[test]
+    fn edits_only_retokenize_from_the_changed_line() {
+        let mut buffer = Buffer::default();
+        buffer.load_str("/*\na\nb\n*/\nfn\n");
+        let mut syntax = Highlighter::new(by_name("rust").unwrap());
+        syntax.update(&buffer, 10);
+        assert_eq!(syntax.cached(), 5);
+        assert_eq!(syntax.spans(4)[0].group, Group::Keyword);
+        buffer.lines[0] = crate::buffer::Line::from_string("//".to_owned());
+        syntax.invalidate(0);
+        syntax.update(&buffer, 2);
+        assert_eq!(syntax.cached(), 2);
+        assert!(syntax.spans(1).is_empty());
+    }
+}
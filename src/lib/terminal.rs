@@ -0,0 +1,147 @@
+use ratatui::termion::{
+    cursor,
+    raw::{IntoRawMode, RawTerminal},
+    screen::{ToAlternateScreen, ToMainScreen},
+};
+use std::io::{Result, Stdout, Write, stdout};
+use std::ops::{Deref, DerefMut};
+use std::panic;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::swap;
+
+pub const FOCUS_IN: &[u8] = b"\x1b[I";
+pub const FOCUS_OUT: &[u8] = b"\x1b[O";
+const ENABLE_FOCUS_EVENTS: &str = "\x1b[?1004h";
+const DISABLE_FOCUS_EVENTS: &str = "\x1b[?1004l";
+
+// the raw handle lives in a static so the panic hook can put the tty back
+// into cooked mode before the default hook prints the message.
+static RAW: Mutex<Option<RawTerminal<Stdout>>> = Mutex::new(None);
+
+pub struct TerminalGuard {
+    stdout: Stdout,
+}
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self> {
+        let raw = stdout().into_raw_mode()?;
+        if let Ok(mut slot) = RAW.lock() {
+            *slot = Some(raw);
+        }
+        let mut stdout = stdout();
+        write!(stdout, "{}{}", ToAlternateScreen, ENABLE_FOCUS_EVENTS)?;
+        stdout.flush()?;
+        Ok(Self { stdout })
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = Stdout;
+    fn deref(&self) -> &Stdout {
+        &self.stdout
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Stdout {
+        &mut self.stdout
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+        if let Ok(mut slot) = RAW.lock() {
+            slot.take();
+        }
+    }
+}
+
+pub fn restore() {
+    let mut out = stdout();
+    let _ = write!(
+        out,
+        "{}{}{}",
+        DISABLE_FOCUS_EVENTS,
+        ToMainScreen,
+        cursor::Show
+    );
+    let _ = out.flush();
+    // try_lock: a panic while the guard is being dropped must not deadlock
+    if let Ok(slot) = RAW.try_lock()
+        && let Some(raw) = slot.as_ref()
+    {
+        let _ = raw.suspend_raw_mode();
+    }
+}
+
+// the unsaved buffers are written from the hook itself, from the snapshot
+// the editor keeps in swap, so nothing depends on unwinding back to main
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default(info);
+        for path in swap::dump_snapshot() {
+            eprintln!("ve: unsaved changes written to {}", path.display());
+        }
+    }));
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ByteCounter(Arc<AtomicUsize>);
+
+impl ByteCounter {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// sits between the ratatui backend and the tty so we can see how much each
+// frame actually costs on the wire
+pub struct CountingWriter<W> {
+    inner: W,
+    counter: ByteCounter,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W, counter: ByteCounter) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.counter.0.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    pub keys: usize,
+    pub bytes: usize,
+    pub last: usize,
+}
+
+impl RenderStats {
+    pub fn record(&mut self, bytes: usize) {
+        self.keys += 1;
+        self.bytes += bytes;
+        self.last = bytes;
+    }
+    pub fn summary(&self) -> String {
+        let average = self.bytes.checked_div(self.keys).unwrap_or(0);
+        format!(
+            "last key {} bytes, {} bytes/key average over {} keys",
+            self.last, average, self.keys
+        )
+    }
+}
@@ -0,0 +1,300 @@
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::command;
+use crate::file;
+
+// themes that ship with ve; $XDG_CONFIG_HOME/ve/themes/NAME.theme wins over
+// a builtin one of the same name
+const BUILTIN: &[(&str, &str)] = &[
+    ("default", include_str!("../../themes/default.theme")),
+    ("dusk", include_str!("../../themes/dusk.theme")),
+];
+
+// every highlight group a theme may set
+pub const GROUPS: &[&str] = &[
+    "Normal",
+    "Comment",
+    "String",
+    "Number",
+    "Constant",
+    "Keyword",
+    "Type",
+    "Title",
+    "Special",
+    "SpecialKey",
+    "NonText",
+    "LineNr",
+    "CursorLineNr",
+    "StatusLine",
+    "StatusLineNC",
+    "VertSplit",
+    "Visual",
+    "Search",
+];
+
+// the 16 ansi colors with xterm's rgb values, in palette order
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").ok();
+        let term = env::var("TERM").ok();
+        Self::from_env(colorterm.as_deref(), term.as_deref())
+    }
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            ColorDepth::TrueColor
+        } else if term.is_some_and(|t| t.contains("256color")) {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+    fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, c) => c,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(to_256((r, g, b))),
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => to_16((r, g, b)),
+            (ColorDepth::Ansi16, Color::Indexed(i)) => to_16(rgb_of_index(i)),
+            (_, c) => c,
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn rgb_of_index(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..16 => ANSI[i as usize].1,
+        16..232 => {
+            let i = i - 16;
+            (
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (i - 232);
+            (level, level, level)
+        }
+    }
+}
+
+// the nearest of the 6x6x6 cube and the 24 grays
+fn to_256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&i| distance(rgb, rgb_of_index(i)))
+        .unwrap_or(16)
+}
+
+fn to_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI.iter()
+        .min_by_key(|(_, c)| distance(rgb, *c))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let n = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb((n >> 16) as u8, (n >> 8) as u8, n as u8));
+    }
+    if let Ok(i) = value.parse::<u8>() {
+        return Some(Color::Indexed(i));
+    }
+    let color = match value.to_ascii_lowercase().as_str() {
+        "none" | "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+fn theme_error(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+// a theme file has one group per line: its name, then fg=COLOR, bg=COLOR and
+// any of bold, italic, underline, reverse and dim. colors are names like
+// "lightblue", 256-color indexes or #rrggbb
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    styles: HashMap<String, Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let (name, source) = BUILTIN[0];
+        Self::parse(name, source).unwrap_or_else(|_| Self {
+            name: name.to_owned(),
+            styles: HashMap::new(),
+        })
+    }
+}
+
+impl Theme {
+    pub fn parse(name: &str, source: &str) -> Result<Self> {
+        let mut styles = HashMap::new();
+        for (i, line) in source.lines().enumerate() {
+            let n = i + 1;
+            let mut words = line.split_whitespace();
+            let Some(group) = words.next().filter(|w| !w.starts_with('#')) else {
+                continue;
+            };
+            if !GROUPS.contains(&group) {
+                return Err(theme_error(n, &format!("unknown group {}", group)));
+            }
+            let mut style = Style::new();
+            for attr in words {
+                let color = |value: &str| {
+                    parse_color(value)
+                        .ok_or_else(|| theme_error(n, &format!("invalid color {}", value)))
+                };
+                style = match attr.split_once('=') {
+                    Some(("fg", value)) => style.fg(color(value)?),
+                    Some(("bg", value)) => style.bg(color(value)?),
+                    None => style.add_modifier(match attr {
+                        "bold" => Modifier::BOLD,
+                        "italic" => Modifier::ITALIC,
+                        "underline" => Modifier::UNDERLINED,
+                        "reverse" => Modifier::REVERSED,
+                        "dim" => Modifier::DIM,
+                        _ => return Err(theme_error(n, &format!("unknown attribute {}", attr))),
+                    }),
+                    _ => return Err(theme_error(n, &format!("unknown attribute {}", attr))),
+                };
+            }
+            styles.insert(group.to_owned(), style);
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            styles,
+        })
+    }
+    // a user theme by that name, or else a builtin one
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(path) =
+            file::config_dir().map(|d| d.join("themes").join(format!("{}.theme", name)))
+            && path.is_file()
+        {
+            return Self::parse(name, &fs::read_to_string(&path)?)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)));
+        }
+        match BUILTIN.iter().find(|(n, _)| *n == name) {
+            Some((name, source)) => Self::parse(name, source),
+            None => Err(command::invalid(format!(
+                "cannot find color scheme {}",
+                name
+            ))),
+        }
+    }
+    pub fn degrade(mut self, depth: ColorDepth) -> Self {
+        for style in self.styles.values_mut() {
+            style.fg = style.fg.map(|c| depth.convert(c));
+            style.bg = style.bg.map(|c| depth.convert(c));
+        }
+        self
+    }
+    pub fn style(&self, group: &str) -> Style {
+        self.styles.get(group).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_themes_parse() {
+        for (name, source) in BUILTIN {
+            assert!(Theme::parse(name, source).is_ok(), "{}", name);
+        }
+        let theme = Theme::parse("t", "# comment\nKeyword fg=#ff0000 bg=4 bold\n").unwrap();
+        assert_eq!(
+            theme.style("Keyword"),
+            Style::new()
+                .fg(Color::Rgb(255, 0, 0))
+                .bg(Color::Indexed(4))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(theme.style("Visual"), Style::default());
+        let err = Theme::parse("t", "Keyword\nKeywrod fg=red\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown group Keywrod");
+        assert!(Theme::parse("t", "Keyword fg=#12\n").is_err());
+        assert!(Theme::load("nope").is_err());
+    }
+
+    #[test]
+    fn colors_degrade_to_what_the_terminal_supports() {
+        assert_eq!(
+            ColorDepth::from_env(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env(None, Some("xterm-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            ColorDepth::from_env(None, Some("linux")),
+            ColorDepth::Ansi16
+        );
+        let theme = Theme::parse("t", "Keyword fg=#d78787 bg=#000000\nType fg=208\n").unwrap();
+        let ansi256 = theme.clone().degrade(ColorDepth::Ansi256);
+        assert_eq!(ansi256.style("Keyword").fg, Some(Color::Indexed(174)));
+        assert_eq!(ansi256.style("Keyword").bg, Some(Color::Indexed(16)));
+        let ansi16 = theme.degrade(ColorDepth::Ansi16);
+        assert_eq!(ansi16.style("Keyword").fg, Some(Color::DarkGray));
+        assert_eq!(ansi16.style("Type").fg, Some(Color::Yellow));
+    }
+}
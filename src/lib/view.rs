@@ -1,36 +1,163 @@
-use crate::buffer::Buffer;
-use ratatui::termion::terminal_size;
-use std::io::{Result, Write};
+use unicode_width::UnicodeWidthStr;
 
-#[derive(Default, Debug)]
+use crate::buffer::{Buffer, Line};
+use crate::display::ListChars;
+
+#[derive(Default, Debug, Clone)]
 pub struct View {
     pub offset_y: usize,
     pub offset_x: usize,
+    pub offset_row: usize,
+    pub list: bool,
+    pub listchars: ListChars,
+    pub wrap: bool,
+    pub showbreak: String,
+    pub breakindent: bool,
+    pub number: bool,
+    pub relativenumber: bool,
+    pub scrolloff: usize,
+}
+
+// one screen row of a line: graphemes start..end, drawn after prefix cells
+// of breakindent and showbreak
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub start: usize,
+    pub end: usize,
+    pub prefix: usize,
 }
+
 impl View {
-    pub fn render<W: Write>(&self, stdout: &mut W, buffer: &Buffer) -> Result<()> {
-        write!(
-            stdout,
-            "{}{}",
-            ratatui::termion::clear::All,
-            ratatui::termion::cursor::Goto(1, 1)
-        )?;
-        let (cols, rows) = terminal_size().unwrap_or((80, 24));
-        let max_cols = cols as usize;
-        let max_rows = rows as usize;
-        let start_line = self.offset_y;
-        let end_line = start_line.saturating_add(max_rows).min(buffer.line_count());
-        for line in &buffer.lines[start_line..end_line] {
-            let start_grapheme = self.offset_x.min(line.grapheme_len());
-            let end_grapheme = usize::min(start_grapheme + max_cols, line.grapheme_len());
-            let start_byte = *line
-                .graphemes
-                .get(start_grapheme)
-                .unwrap_or(&line.raw.len());
-            let end_byte = *line.graphemes.get(end_grapheme).unwrap_or(&line.raw.len());
-            let visible = &line.raw[start_byte..end_byte];
-            write!(stdout, "{}\r\n", visible)?;
+    pub fn reset_scroll(&mut self) {
+        self.offset_x = 0;
+        self.offset_y = 0;
+        self.offset_row = 0;
+    }
+    pub fn indent(&self, line: &Line, tabstop: usize) -> usize {
+        let blank = (0..line.grapheme_len())
+            .take_while(|&i| matches!(line.grapheme_at(i), Some(" " | "\t")))
+            .count();
+        line.column_of(blank, tabstop)
+    }
+    // cells in front of every continuation row; dropped when they would leave
+    // no room for text
+    fn break_prefix(&self, line: &Line, tabstop: usize, width: usize) -> usize {
+        let indent = if self.breakindent {
+            self.indent(line, tabstop)
+        } else {
+            0
+        };
+        let prefix = indent + self.showbreak.width();
+        if prefix < width { prefix } else { 0 }
+    }
+    // without wrap a line is always one row; with it, rows end after the last
+    // blank that fits and only words longer than a row are split. a single
+    // space may hang past the edge so the next row does not start with it
+    pub fn rows(&self, line: &Line, tabstop: usize, width: usize) -> Vec<Row> {
+        let len = line.grapheme_len();
+        if !self.wrap {
+            return vec![Row {
+                start: 0,
+                end: len,
+                prefix: 0,
+            }];
+        }
+        let width = width.max(1);
+        let spans = line.spans(tabstop).collect::<Vec<_>>();
+        let continuation = self.break_prefix(line, tabstop, width);
+        let mut rows = Vec::new();
+        let mut start = 0;
+        let mut prefix = 0;
+        while start < len || rows.is_empty() {
+            let room = width - prefix;
+            let first_col = spans.get(start).map_or(0, |s| s.1);
+            let mut end = start;
+            let mut after_blank = None;
+            while let Some(&(grapheme, col, w)) = spans.get(end) {
+                let fits = col + w - first_col <= room;
+                let hangs = grapheme == " " && col - first_col == room;
+                if end > start && !fits && !hangs {
+                    break;
+                }
+                end += 1;
+                if matches!(grapheme, " " | "\t") {
+                    after_blank = Some(end);
+                }
+            }
+            if end < len
+                && let Some(blank) = after_blank
+            {
+                end = blank;
+            }
+            rows.push(Row { start, end, prefix });
+            start = end;
+            prefix = continuation;
+        }
+        rows
+    }
+    pub fn row_count(&self, buffer: &Buffer, y: usize, width: usize) -> usize {
+        match buffer.lines.get(y) {
+            Some(line) if self.wrap => self.rows(line, buffer.tabstop, width).len(),
+            _ => 1,
         }
-        stdout.flush()
+    }
+}
+
+// the row holding grapheme x; the end of the line belongs to the last row
+pub fn row_of(rows: &[Row], x: usize) -> usize {
+    rows.iter()
+        .position(|row| x < row.end)
+        .unwrap_or(rows.len().saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(view: &View, text: &str, width: usize) -> Vec<(usize, usize, usize)> {
+        let line = Line::from_string(text.to_owned());
+        view.rows(&line, 8, width)
+            .iter()
+            .map(|r| (r.start, r.end, r.prefix))
+            .collect()
+    }
+
+    #[test]
+    fn wrap_breaks_after_blanks() {
+        let mut view = View {
+            wrap: true,
+            ..View::default()
+        };
+        assert_eq!(
+            ranges(&view, "one two three", 8),
+            vec![(0, 8, 0), (8, 13, 0)]
+        );
+        // a word longer than the row is split where it runs out of room
+        assert_eq!(
+            ranges(&view, "abcdefghij", 4),
+            vec![(0, 4, 0), (4, 8, 0), (8, 10, 0)]
+        );
+        assert_eq!(ranges(&view, "", 4), vec![(0, 0, 0)]);
+        view.showbreak = "> ".to_owned();
+        view.breakindent = true;
+        assert_eq!(
+            ranges(&view, "  ab cd ef", 6),
+            vec![(0, 5, 0), (5, 8, 4), (8, 10, 4)]
+        );
+        view.wrap = false;
+        assert_eq!(ranges(&view, "one two three", 8), vec![(0, 13, 0)]);
+    }
+
+    #[test]
+    fn wide_characters_move_to_the_next_row_whole() {
+        let view = View {
+            wrap: true,
+            ..View::default()
+        };
+        assert_eq!(ranges(&view, "a漢字", 4), vec![(0, 2, 0), (2, 3, 0)]);
+        assert_eq!(
+            row_of(&view.rows(&Line::from_string("a漢字".into()), 8, 4), 3),
+            1
+        );
     }
 }
@@ -0,0 +1,561 @@
+use ratatui::buffer::Buffer as Screen;
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::Style;
+use ratatui::widgets::{StatefulWidget, Widget};
+use std::borrow::Cow;
+use unicode_width::UnicodeWidthStr;
+
+use crate::buffer::Buffer;
+use crate::display;
+use crate::editor::Editor;
+use crate::encoding;
+use crate::gutter::Gutter;
+use crate::keyhandler::Mode;
+use crate::syntax::Highlighter;
+use crate::theme::Theme;
+use crate::view::View;
+
+const MORE: &str = "press any key to continue";
+
+pub struct BufferView<'a> {
+    buffer: &'a Buffer,
+    view: &'a View,
+    theme: &'a Theme,
+    gutter: Gutter,
+    syntax: Option<&'a Highlighter>,
+}
+
+impl<'a> BufferView<'a> {
+    pub fn new(buffer: &'a Buffer, view: &'a View, theme: &'a Theme) -> Self {
+        Self {
+            buffer,
+            view,
+            theme,
+            gutter: Gutter::default(),
+            syntax: None,
+        }
+    }
+    pub fn highlight(mut self, syntax: &'a Highlighter) -> Self {
+        self.syntax = Some(syntax);
+        self
+    }
+    pub fn gutter(mut self, gutter: Gutter) -> Self {
+        self.gutter = gutter;
+        self
+    }
+    // the highlight of every grapheme of line y, from the byte ranges the
+    // highlighter found
+    fn styles(&self, y: usize, spans: &[(&str, usize, usize)]) -> Vec<Style> {
+        let highlights = self.syntax.map_or(&[][..], |s| s.spans(y));
+        let mut next = highlights.iter().peekable();
+        let mut byte = 0;
+        spans
+            .iter()
+            .map(|(grapheme, _, _)| {
+                while next.next_if(|h| h.end <= byte).is_some() {}
+                let style = match next.peek() {
+                    Some(h) if h.start <= byte => self.theme.style(h.group.name()),
+                    _ => Style::default(),
+                };
+                byte += grapheme.len();
+                style
+            })
+            .collect()
+    }
+    // what goes on screen for one grapheme, and whether it is a stand-in
+    // rather than the text itself
+    fn glyph(&self, grapheme: &'a str, width: usize, trailing: bool) -> (Cow<'a, str>, bool) {
+        let lcs = &self.view.listchars;
+        let list = self.view.list;
+        match grapheme {
+            "\t" => match lcs.tab.filter(|_| list) {
+                Some((first, fill)) => {
+                    let fill = std::iter::repeat_n(fill, width.saturating_sub(1));
+                    (std::iter::once(first).chain(fill).collect(), true)
+                }
+                None => (" ".repeat(width).into(), false),
+            },
+            " " if list && trailing && lcs.trail.is_some() => {
+                (lcs.trail.unwrap_or(' ').to_string().into(), true)
+            }
+            "\u{a0}" if list && lcs.nbsp.is_some() => {
+                (lcs.nbsp.unwrap_or(' ').to_string().into(), true)
+            }
+            g if g.chars().next().and_then(encoding::raw_byte).is_some() => {
+                ("\u{FFFD}".into(), true)
+            }
+            g => match display::notation(g) {
+                Some(notation) => (notation.into(), true),
+                None => (g.into(), false),
+            },
+        }
+    }
+}
+
+// view.offset_x is a display column, so a wide character cut by the left
+// edge shows as blanks and one that does not fit at the right edge is left out.
+// with wrap on, every line is drawn as the rows View::rows splits it into.
+// the gutter takes the left of the area and the text gets the rest
+impl Widget for BufferView<'_> {
+    fn render(self, area: Rect, screen: &mut Screen) {
+        let margin = self.gutter.width().min(area.width);
+        let gutter_x = area.x;
+        let area = Rect {
+            x: area.x + margin,
+            width: area.width - margin,
+            ..area
+        };
+        let special = self.theme.style("SpecialKey");
+        let non_text = self.theme.style("NonText");
+        let tabstop = self.buffer.tabstop;
+        let width = area.width as usize;
+        let start_line = self.view.offset_y.min(self.buffer.line_count());
+        let mut y = area.y;
+        let mut skip = self.view.offset_row;
+        for (line_y, line) in self.buffer.lines.iter().enumerate().skip(start_line) {
+            if y >= area.bottom() {
+                break;
+            }
+            let trail = (0..line.grapheme_len())
+                .rev()
+                .take_while(|&i| line.grapheme_at(i) == Some(" "))
+                .count();
+            let trail_from = line.grapheme_len() - trail;
+            let spans = line.spans(tabstop).collect::<Vec<_>>();
+            let styles = self.styles(line_y, &spans);
+            let rows = self.view.rows(line, tabstop, width);
+            for (n, row) in rows.iter().enumerate().skip(std::mem::take(&mut skip)) {
+                if y >= area.bottom() {
+                    break;
+                }
+                if row.start == 0 && margin > 0 {
+                    self.gutter.render(screen, gutter_x, y, line_y, self.theme);
+                }
+                let base = if self.view.wrap {
+                    line.column_of(row.start, tabstop)
+                } else {
+                    self.view.offset_x
+                };
+                let left = area.x + row.prefix as u16;
+                let room = width.saturating_sub(row.prefix);
+                if row.prefix > 0 {
+                    let showbreak = &self.view.showbreak;
+                    let x = left - showbreak.width() as u16;
+                    screen.set_stringn(x, y, showbreak, showbreak.width(), non_text);
+                }
+                let mut eol = line.column_of(row.start, tabstop);
+                for (i, &(grapheme, col, cells)) in
+                    spans.iter().enumerate().take(row.end).skip(row.start)
+                {
+                    let end = col + cells;
+                    eol = end;
+                    if end > base + room {
+                        break;
+                    }
+                    let x = left + col.saturating_sub(base) as u16;
+                    if col >= base {
+                        let (text, stand_in) = self.glyph(grapheme, cells, i >= trail_from);
+                        let style = if stand_in { special } else { styles[i] };
+                        screen.set_stringn(x, y, text, cells, style);
+                    } else if end > base {
+                        // whatever is cut by the left edge becomes blanks
+                        let blank = end - base;
+                        screen.set_stringn(x, y, " ".repeat(blank), blank, Style::default());
+                    }
+                }
+                if n + 1 == rows.len()
+                    && self.view.list
+                    && let Some(c) = self.view.listchars.eol
+                    && eol >= base
+                    && eol < base + room
+                {
+                    let x = left + (eol - base) as u16;
+                    screen.set_stringn(x, y, c.to_string(), 1, non_text);
+                }
+                y += 1;
+            }
+        }
+    }
+}
+
+pub struct StatusLine<'a> {
+    message: &'a str,
+}
+
+impl<'a> StatusLine<'a> {
+    pub fn new(message: &'a str) -> Self {
+        Self { message }
+    }
+}
+
+impl Widget for StatusLine<'_> {
+    fn render(self, area: Rect, screen: &mut Screen) {
+        screen.set_stringn(
+            area.x,
+            area.y,
+            self.message,
+            area.width as usize,
+            Style::default(),
+        );
+    }
+}
+
+pub struct CommandLine<'a> {
+    input: &'a str,
+}
+
+impl<'a> CommandLine<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input }
+    }
+    pub fn cursor(&self, area: Rect) -> Position {
+        let col = self.input.width() as u16 + 1;
+        Position::new(area.x + col.min(area.width), area.y)
+    }
+}
+
+impl Widget for CommandLine<'_> {
+    fn render(self, area: Rect, screen: &mut Screen) {
+        screen.set_stringn(area.x, area.y, ":", area.width as usize, Style::default());
+        if area.width > 1 {
+            screen.set_stringn(
+                area.x + 1,
+                area.y,
+                self.input,
+                area.width as usize - 1,
+                Style::default(),
+            );
+        }
+    }
+}
+
+// the pager shown after commands like :recover; the newest lines win when
+// there are more of them than fit
+pub struct Messages<'a> {
+    lines: &'a [String],
+}
+
+impl<'a> Messages<'a> {
+    pub fn new(lines: &'a [String]) -> Self {
+        Self { lines }
+    }
+}
+
+impl Widget for Messages<'_> {
+    fn render(self, area: Rect, screen: &mut Screen) {
+        let max_rows = area.height as usize;
+        let skip = self.lines.len().saturating_sub(max_rows);
+        let top = max_rows.saturating_sub(self.lines.len()) as u16;
+        for (i, msg) in self.lines.iter().skip(skip).enumerate() {
+            let row = area.y + top + i as u16;
+            screen.set_stringn(area.x, row, msg, area.width as usize, Style::default());
+        }
+    }
+}
+
+// the whole editor as one widget, for embedding ve in another ratatui app:
+// render it with the Editor as state, then place the terminal cursor at
+// Editor::screen_cursor
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EditorView;
+
+impl StatefulWidget for EditorView {
+    type State = Editor;
+    fn render(self, area: Rect, screen: &mut Screen, editor: &mut Editor) {
+        screen.set_style(area, editor.theme.style("Normal"));
+        let [text, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        // with one window the editor's status line is all there is; with more
+        // every window gets its own below it
+        editor.sync_windows();
+        let (windows, separators) = editor.windows.layout.layout(text);
+        let split = windows.len() > 1;
+        let own = match windows.iter().find(|(id, _)| *id == editor.windows.current) {
+            Some(&(_, area)) if split => window_text(area),
+            _ => text,
+        };
+        editor.windows.areas = windows.clone();
+        let (gutter, body) = text_area(
+            &editor.view,
+            &editor.buffer,
+            editor.hex.is_some(),
+            editor.cursor.y,
+            own,
+        );
+        // the viewport is what is left for text, so scrolling and wrapping
+        // never count the gutter
+        if editor.viewport() != body.as_size() {
+            editor.resize(body.as_size());
+        }
+        let end = follow_others(editor, &windows);
+        editor.refresh_syntax(end.max(editor.view.offset_y + body.height as usize));
+        if !editor.messages.is_empty() {
+            Messages::new(&editor.messages).render(text, screen);
+            StatusLine::new(MORE).render(status, screen);
+            let col = (MORE.len() as u16).min(status.width);
+            editor.screen_cursor = Some(Position::new(status.x + col, status.y));
+            return;
+        }
+        render_others(editor, &windows, screen);
+        match &editor.hex {
+            Some(hex) => hex.render(own, screen),
+            None => BufferView::new(&editor.buffer, &editor.view, &editor.theme)
+                .gutter(gutter)
+                .highlight(&editor.syntax)
+                .render(own, screen),
+        }
+        if split {
+            let fill = editor.theme.style("VertSplit");
+            for separator in separators {
+                for y in separator.top()..separator.bottom() {
+                    screen.set_stringn(separator.x, y, "│", 1, fill);
+                }
+            }
+            // a terminal too small for every window leaves some empty
+            for &(id, area) in windows.iter().filter(|(_, a)| !a.is_empty()) {
+                let group = match id == editor.windows.current {
+                    true => "StatusLine",
+                    false => "StatusLineNC",
+                };
+                let row = Rect::new(area.x, area.bottom().saturating_sub(1), area.width, 1);
+                screen.set_style(row, editor.theme.style(group));
+                screen.set_stringn(
+                    row.x,
+                    row.y,
+                    window_name(editor, id),
+                    row.width as usize,
+                    Style::default(),
+                );
+            }
+        }
+        let (x, y) = match &editor.hex {
+            Some(hex) => hex.cursor_position(),
+            None => editor
+                .cursor
+                .screen_position(&editor.view, &editor.buffer, own.as_size()),
+        };
+        let mut cursor = Position::new(body.x + x, body.y + y);
+        if editor.get_mode() == Mode::Command {
+            let line = CommandLine::new(&editor.command_line);
+            cursor = line.cursor(status);
+            line.render(status, screen);
+        } else if let Some(prompt) = &editor.prompt {
+            StatusLine::new(prompt.message()).render(status, screen);
+        } else {
+            StatusLine::new(editor.status()).render(status, screen);
+        }
+        editor.screen_cursor = Some(cursor);
+    }
+}
+
+// a window without its status line
+fn window_text(area: Rect) -> Rect {
+    Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    }
+}
+
+// the gutter of a window and the room it leaves for text
+fn text_area(view: &View, buffer: &Buffer, hex: bool, y: usize, area: Rect) -> (Gutter, Rect) {
+    let gutter = match hex {
+        true => Gutter::default(),
+        false => Gutter::new(view, buffer.line_count(), y),
+    };
+    let [_, body] =
+        Layout::horizontal([Constraint::Length(gutter.width()), Constraint::Min(0)]).areas(area);
+    (gutter, body)
+}
+
+// scrolls the other windows to their cursors and highlights what they show
+// of hidden buffers; returns how far down the current buffer is shown in them
+fn follow_others(editor: &mut Editor, windows: &[(usize, Rect)]) -> usize {
+    let mut end = 0;
+    for window in editor.windows.others.iter_mut() {
+        let Some(&(_, area)) = windows.iter().find(|(id, _)| *id == window.id) else {
+            continue;
+        };
+        let area = window_text(area);
+        match editor.buffers.get_mut(window.buffer) {
+            Some(hidden) => {
+                let hex = hidden.hex.is_some();
+                let (_, body) = text_area(&window.view, &hidden.buffer, hex, window.cursor.y, area);
+                window
+                    .cursor
+                    .follow(&mut window.view, &hidden.buffer, body.as_size());
+                hidden.refresh_syntax(window.view.offset_y + body.height as usize);
+            }
+            None => {
+                let hex = editor.hex.is_some();
+                let (_, body) = text_area(&window.view, &editor.buffer, hex, window.cursor.y, area);
+                window
+                    .cursor
+                    .follow(&mut window.view, &editor.buffer, body.as_size());
+                end = end.max(window.view.offset_y + body.height as usize);
+            }
+        }
+    }
+    end
+}
+
+fn render_others(editor: &Editor, windows: &[(usize, Rect)], screen: &mut Screen) {
+    for window in &editor.windows.others {
+        let Some(&(_, area)) = windows.iter().find(|(id, _)| *id == window.id) else {
+            continue;
+        };
+        let area = window_text(area);
+        let (buffer, hex, syntax) = match editor.buffers.get(window.buffer) {
+            Some(hidden) => (&hidden.buffer, hidden.hex.as_ref(), &hidden.syntax),
+            None => (&editor.buffer, editor.hex.as_ref(), &editor.syntax),
+        };
+        match hex {
+            Some(hex) => hex.render(area, screen),
+            None => {
+                let (gutter, _) = text_area(&window.view, buffer, false, window.cursor.y, area);
+                BufferView::new(buffer, &window.view, &editor.theme)
+                    .gutter(gutter)
+                    .highlight(syntax)
+                    .render(area, screen);
+            }
+        }
+    }
+}
+
+// what the status line of a window says: its file and whether it changed
+fn window_name(editor: &Editor, id: usize) -> String {
+    let buffer = match id == editor.windows.current {
+        true => editor.buffers.current,
+        false => editor
+            .windows
+            .others
+            .iter()
+            .find(|w| w.id == id)
+            .map_or(editor.buffers.current, |w| w.buffer),
+    };
+    let (name, modified) = match editor.buffers.get(buffer) {
+        Some(hidden) => (
+            hidden
+                .file
+                .as_ref()
+                .map_or("[No Name]".to_owned(), |p| p.display().to_string()),
+            hidden.is_modified(),
+        ),
+        None => (editor.buffer_name(), editor.is_modified()),
+    };
+    match modified {
+        true => format!("{} [+]", name),
+        false => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::screen_rows as rows;
+    use crate::input::Key;
+    use ratatui::style::Color;
+
+    #[test]
+    fn editor_view_renders_into_any_area() {
+        let mut editor = Editor::default();
+        editor.buffer.load_str("first\nsecond line\n");
+        editor.cursor.y = 1;
+        editor.cursor.x = 3;
+        editor.set_status("hello".to_owned());
+        let mut screen = Screen::empty(Rect::new(0, 0, 20, 6));
+        let area = Rect::new(2, 1, 8, 3);
+        EditorView.render(area, &mut screen, &mut editor);
+        assert_eq!(
+            rows(&screen),
+            vec!["", "  first", "  second l", "  hello", "", ""]
+        );
+        assert_eq!(editor.screen_cursor, Some(Position::new(5, 2)));
+    }
+
+    #[test]
+    fn wide_characters_are_cut_by_display_column() {
+        let mut buffer = Buffer::default();
+        buffer.load_str("ab漢字cd\n");
+        let view = View {
+            offset_x: 3,
+            ..View::default()
+        };
+        let mut screen = Screen::empty(Rect::new(0, 0, 4, 1));
+        BufferView::new(&buffer, &view, &Theme::default()).render(screen.area, &mut screen);
+        // 漢 covers columns 2-3 and 字 4-5, so the view starts half way into 漢
+        assert_eq!(rows(&screen), vec![" 字c"]);
+        let view = View::default();
+        let mut screen = Screen::empty(Rect::new(0, 0, 5, 1));
+        BufferView::new(&buffer, &view, &Theme::default()).render(screen.area, &mut screen);
+        assert_eq!(rows(&screen), vec!["ab漢"]);
+    }
+
+    #[test]
+    fn bad_bytes_show_as_replacement_characters() {
+        let mut buffer = Buffer::default();
+        buffer.load_bytes(b"\xC3\xA9\xFFb\n");
+        let view = View::default();
+        let mut screen = Screen::empty(Rect::new(0, 0, 5, 1));
+        BufferView::new(&buffer, &view, &Theme::default()).render(screen.area, &mut screen);
+        assert_eq!(rows(&screen), vec!["é\u{FFFD}b"]);
+    }
+
+    #[test]
+    fn gutter_narrows_the_viewport() {
+        let mut editor = Editor::default();
+        editor.buffer.load_str("abcdefgh\n");
+        editor.view.number = true;
+        let mut screen = Screen::empty(Rect::new(0, 0, 8, 2));
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(editor.viewport().width, 4);
+        for _ in 0..5 {
+            editor.handle_key(Key::Right).unwrap();
+        }
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(rows(&screen)[0], "  1 cdef");
+        assert_eq!(editor.screen_cursor, Some(Position::new(7, 0)));
+    }
+
+    #[test]
+    fn highlighting_follows_edits() {
+        let mut editor = Editor::default();
+        editor.buffer.load_str("fn x\n");
+        editor.set_option("syntax=rust").unwrap();
+        let mut screen = Screen::empty(Rect::new(0, 0, 10, 3));
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(screen[(0, 0)].fg, Color::Yellow);
+        assert_eq!(screen[(3, 0)].fg, Color::Reset);
+        for key in [Key::Char('i'), Key::Char('/'), Key::Char('/')] {
+            editor.handle_key(key).unwrap();
+        }
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(rows(&screen)[0], "//fn x");
+        assert_eq!(screen[(2, 0)].fg, Color::Cyan);
+    }
+
+    #[test]
+    fn split_needs_a_line_and_a_status_line_for_both() {
+        let mut editor = Editor::default();
+        let mut screen = Screen::empty(Rect::new(0, 0, 10, 4));
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        let err = editor.execute_command("sp").unwrap_err();
+        assert_eq!(err.to_string(), "not enough room");
+        let mut screen = Screen::empty(Rect::new(0, 0, 10, 5));
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        editor.execute_command("sp").unwrap();
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(rows(&screen)[1], "[No Name]");
+        assert_eq!(rows(&screen)[3], "[No Name]");
+    }
+
+    #[test]
+    fn command_line_owns_the_cursor() {
+        let mut editor = Editor::default();
+        editor.handle_key(Key::Char(':')).unwrap();
+        editor.handle_key(Key::Char('w')).unwrap();
+        let mut screen = Screen::empty(Rect::new(0, 0, 10, 3));
+        EditorView.render(screen.area, &mut screen, &mut editor);
+        assert_eq!(rows(&screen)[2], ":w");
+        assert_eq!(editor.screen_cursor, Some(Position::new(2, 2)));
+    }
+}
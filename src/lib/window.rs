@@ -0,0 +1,360 @@
+use ratatui::layout::{Position, Rect};
+
+use crate::cursor::Cursor;
+use crate::view::View;
+
+// how the children of a split sit: stacked top to bottom (:split) or side by
+// side (:vsplit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stack {
+    Rows,
+    Columns,
+}
+
+// the window layout: leaves are window ids and every split keeps the size of
+// each child in cells along its stack, as of the last layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Window(usize),
+    Split(Stack, Vec<(Layout, u16)>),
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Window(1)
+    }
+}
+
+impl Layout {
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Window(w) => *w == id,
+            Layout::Split(_, children) => children.iter().any(|(c, _)| c.contains(id)),
+        }
+    }
+    // the window ids from top left to bottom right
+    pub fn ids(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(id) => vec![*id],
+            Layout::Split(_, children) => children.iter().flat_map(|(c, _)| c.ids()).collect(),
+        }
+    }
+    // puts window new before target, taking half of its room
+    pub fn split(&mut self, target: usize, new: usize, stack: Stack) {
+        match self {
+            Layout::Window(id) if *id == target => {
+                *self = Layout::Split(
+                    stack,
+                    vec![(Layout::Window(new), 1), (Layout::Window(target), 1)],
+                );
+            }
+            Layout::Window(_) => {}
+            Layout::Split(own, children) => {
+                let direct = children
+                    .iter()
+                    .position(|(c, _)| *c == Layout::Window(target));
+                match direct {
+                    Some(i) if *own == stack => {
+                        // sizes not laid out yet are too small to halve
+                        if children[i].1 < 2 {
+                            children.iter_mut().for_each(|(_, size)| *size *= 2);
+                        }
+                        let size = children[i].1;
+                        children[i].1 = size - size / 2;
+                        children.insert(i, (Layout::Window(new), size / 2));
+                    }
+                    _ => {
+                        for (child, _) in children.iter_mut() {
+                            child.split(target, new, stack);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // takes window id out; its room goes to the window next to it
+    pub fn remove(&mut self, id: usize) {
+        let Layout::Split(_, children) = self else {
+            return;
+        };
+        if let Some(i) = children.iter().position(|(c, _)| *c == Layout::Window(id)) {
+            let (_, size) = children.remove(i);
+            let next = i.min(children.len().saturating_sub(1));
+            if let Some((_, room)) = children.get_mut(next) {
+                *room += size;
+            }
+        } else {
+            for (child, _) in children.iter_mut() {
+                child.remove(id);
+            }
+        }
+        // a split left with one child is replaced by it
+        if children.len() == 1 {
+            let (only, _) = children.remove(0);
+            *self = only;
+        }
+    }
+    // grows window id by delta cells along stack, taking them from the
+    // neighbour in the innermost split of that kind
+    pub fn resize(&mut self, id: usize, stack: Stack, delta: i32) -> bool {
+        let Layout::Split(own, children) = self else {
+            return false;
+        };
+        let Some(i) = children.iter().position(|(c, _)| c.contains(id)) else {
+            return false;
+        };
+        if children[i].0.resize(id, stack, delta) {
+            return true;
+        }
+        if *own != stack || children.len() < 2 {
+            return false;
+        }
+        let j = if i + 1 < children.len() { i + 1 } else { i - 1 };
+        let min = children[i].0.min_size(stack) as i32;
+        let other = children[j].0.min_size(stack) as i32;
+        let total = (children[i].1 + children[j].1) as i32;
+        let size = (children[i].1 as i32 + delta).clamp(min, (total - other).max(min));
+        children[i].1 = size as u16;
+        children[j].1 = (total - size).max(0) as u16;
+        true
+    }
+    // the fewest cells this part of the layout takes along stack: a line of
+    // text and a status line for every window, a column for every window and
+    // one between side by side windows
+    fn min_size(&self, stack: Stack) -> u16 {
+        match self {
+            Layout::Window(_) => match stack {
+                Stack::Rows => 2,
+                Stack::Columns => 1,
+            },
+            Layout::Split(own, children) => {
+                let sizes = children.iter().map(|(c, _)| c.min_size(stack));
+                match *own == stack {
+                    true => sizes.sum::<u16>() + gaps(*own, children.len()),
+                    false => sizes.max().unwrap_or(0),
+                }
+            }
+        }
+    }
+    // makes every split share its room evenly
+    pub fn equalize(&mut self) {
+        if let Layout::Split(_, children) = self {
+            for (child, size) in children.iter_mut() {
+                child.equalize();
+                *size = 1;
+            }
+        }
+    }
+    // the area of every window, each including its status line, and the
+    // columns between side by side windows. the stored sizes are scaled to
+    // the room there is, so they stay in cells
+    pub fn layout(&mut self, area: Rect) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        let mut windows = Vec::new();
+        let mut separators = Vec::new();
+        self.place(area, &mut windows, &mut separators);
+        (windows, separators)
+    }
+    fn place(&mut self, area: Rect, windows: &mut Vec<(usize, Rect)>, separators: &mut Vec<Rect>) {
+        let (stack, children) = match self {
+            Layout::Window(id) => {
+                windows.push((*id, area));
+                return;
+            }
+            Layout::Split(stack, children) => (*stack, children),
+        };
+        let room = match stack {
+            Stack::Rows => area.height,
+            Stack::Columns => area.width,
+        }
+        .saturating_sub(gaps(stack, children.len()));
+        fit(children, room, stack);
+        let mut offset = 0;
+        let count = children.len();
+        for (i, (child, size)) in children.iter_mut().enumerate() {
+            let rect = match stack {
+                Stack::Rows => Rect::new(area.x, area.y + offset, area.width, *size),
+                Stack::Columns => Rect::new(area.x + offset, area.y, *size, area.height),
+            }
+            .intersection(area);
+            child.place(rect, windows, separators);
+            offset += *size;
+            if stack == Stack::Columns && i + 1 < count {
+                separators
+                    .push(Rect::new(area.x + offset, area.y, 1, area.height).intersection(area));
+                offset += 1;
+            }
+        }
+    }
+}
+
+// the separator columns between side by side windows
+fn gaps(stack: Stack, count: usize) -> u16 {
+    match stack {
+        Stack::Rows => 0,
+        Stack::Columns => count.saturating_sub(1) as u16,
+    }
+}
+
+// gives every child its minimum and shares the rest of room by how much
+// each had above its own; evenly when none had more. a terminal too small
+// for all of them leaves the last ones empty
+fn fit(children: &mut [(Layout, u16)], room: u16, stack: Stack) {
+    let mins = children
+        .iter()
+        .map(|(c, _)| c.min_size(stack))
+        .collect::<Vec<_>>();
+    let spare = room.saturating_sub(mins.iter().sum()) as u32;
+    let extras = children
+        .iter()
+        .zip(&mins)
+        .map(|((_, size), min)| size.saturating_sub(*min) as u32)
+        .collect::<Vec<_>>();
+    let even = extras.iter().all(|&e| e == 0);
+    let total = match even {
+        true => children.len() as u32,
+        false => extras.iter().sum(),
+    };
+    let mut left = room;
+    let count = children.len();
+    for (i, (_, size)) in children.iter_mut().enumerate() {
+        let extra = if even { 1 } else { extras[i] };
+        let want = match i + 1 == count {
+            true => left,
+            false => mins[i] + (extra * spare / total) as u16,
+        };
+        *size = want.min(left);
+        left -= *size;
+    }
+}
+
+// a window other than the current one; the current window's view and cursor
+// are the editor's own
+#[derive(Debug, Default)]
+pub struct Window {
+    pub id: usize,
+    pub buffer: usize,
+    pub view: View,
+    pub cursor: Cursor,
+}
+
+#[derive(Debug)]
+pub struct WindowList {
+    pub current: usize,
+    pub layout: Layout,
+    pub others: Vec<Window>,
+    // where each window was drawn last, for moving between them
+    pub areas: Vec<(usize, Rect)>,
+    next: usize,
+}
+
+impl Default for WindowList {
+    fn default() -> Self {
+        Self {
+            current: 1,
+            layout: Layout::default(),
+            others: Vec::new(),
+            areas: Vec::new(),
+            next: 2,
+        }
+    }
+}
+
+impl WindowList {
+    pub fn new_id(&mut self) -> usize {
+        self.next += 1;
+        self.next - 1
+    }
+    pub fn take(&mut self, id: usize) -> Option<Window> {
+        let i = self.others.iter().position(|w| w.id == id)?;
+        Some(self.others.remove(i))
+    }
+    pub fn area(&self, id: usize) -> Option<Rect> {
+        self.areas.iter().find(|(w, _)| *w == id).map(|(_, a)| *a)
+    }
+    // the window next to the current one in the direction of h, j, k or l
+    pub fn neighbour(&self, direction: char) -> Option<usize> {
+        let area = self.area(self.current)?;
+        let point = match direction {
+            'h' => Position::new(area.x.checked_sub(2)?, area.y),
+            'l' => Position::new(area.right() + 1, area.y),
+            'k' => Position::new(area.x, area.y.checked_sub(1)?),
+            'j' => Position::new(area.x, area.bottom()),
+            _ => return None,
+        };
+        self.areas
+            .iter()
+            .find(|(_, a)| a.contains(point))
+            .map(|(id, _)| *id)
+    }
+    // whether the current window can be split with every window still
+    // getting a line of text and a status line, going by the last layout
+    pub fn can_split(&self, stack: Stack) -> bool {
+        let Some(room) = self.areas.iter().map(|(_, a)| *a).reduce(|a, b| a.union(b)) else {
+            return true;
+        };
+        let mut layout = self.layout.clone();
+        layout.split(self.current, self.next, stack);
+        layout.min_size(Stack::Rows) <= room.height && layout.min_size(Stack::Columns) <= room.width
+    }
+    // the window count places after the current one in layout order
+    pub fn cycle(&self, count: isize) -> usize {
+        let ids = self.layout.ids();
+        let i = ids.iter().position(|&id| id == self.current).unwrap_or(0);
+        ids[(i as isize + count).rem_euclid(ids.len() as isize) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_share_the_area() {
+        let mut layout = Layout::default();
+        layout.split(1, 2, Stack::Rows);
+        layout.split(2, 3, Stack::Columns);
+        let (windows, separators) = layout.layout(Rect::new(0, 0, 21, 10));
+        assert_eq!(
+            windows,
+            [
+                (3, Rect::new(0, 0, 10, 5)),
+                (2, Rect::new(11, 0, 10, 5)),
+                (1, Rect::new(0, 5, 21, 5))
+            ]
+        );
+        assert_eq!(separators, [Rect::new(10, 0, 1, 5)]);
+        assert!(layout.resize(1, Stack::Rows, 2));
+        assert!(layout.resize(3, Stack::Columns, -4));
+        let (windows, _) = layout.layout(Rect::new(0, 0, 21, 10));
+        assert_eq!(windows[0].1, Rect::new(0, 0, 6, 3));
+        assert_eq!(windows[2].1, Rect::new(0, 3, 21, 7));
+        layout.remove(3);
+        layout.remove(1);
+        assert_eq!(layout, Layout::Window(2));
+    }
+
+    #[test]
+    fn windows_keep_a_line_and_a_status_line() {
+        let mut windows = WindowList {
+            areas: vec![(1, Rect::new(0, 0, 10, 5))],
+            ..WindowList::default()
+        };
+        assert!(windows.can_split(Stack::Rows));
+        let id = windows.new_id();
+        windows.layout.split(1, id, Stack::Rows);
+        assert!(windows.layout.resize(1, Stack::Rows, 10));
+        let (areas, _) = windows.layout.layout(Rect::new(0, 0, 10, 5));
+        assert_eq!(
+            areas,
+            [(2, Rect::new(0, 0, 10, 2)), (1, Rect::new(0, 2, 10, 3))]
+        );
+        windows.areas = areas;
+        assert!(!windows.can_split(Stack::Rows));
+        assert!(windows.can_split(Stack::Columns));
+        // a terminal shrunk below that leaves the last window empty
+        let (areas, _) = windows.layout.layout(Rect::new(0, 0, 10, 3));
+        assert_eq!(areas[1].1.height, 1);
+        let (areas, _) = windows.layout.layout(Rect::new(0, 0, 10, 1));
+        assert_eq!(areas[1].1.height, 0);
+    }
+}
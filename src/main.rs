@@ -1,25 +1,38 @@
+use ratatui::Terminal;
+use ratatui::backend::TermionBackend;
 use std::env;
-use std::io::Result;
-use std::path::PathBuf;
-use ratatui::termion::{
-    cursor,
-    raw::{IntoRawMode},
-    screen::{ToAlternateScreen, ToMainScreen},
-};
-use std::io::{Write, stdout};
+use std::io::{Result, stdout};
+use std::process;
 use ve::Editor;
+use ve::args::{self, Action};
+use ve::terminal::{self, CountingWriter, TerminalGuard};
 
 fn main() -> Result<()> {
-    let mut stdout = stdout().into_raw_mode()?;
-    write!(stdout, "{}", ToAlternateScreen)?;
+    let args = match args::parse(env::args().skip(1)) {
+        Ok(Action::Edit(args)) => args,
+        Ok(Action::Help) => {
+            print!("{}", args::USAGE);
+            return Ok(());
+        }
+        Ok(Action::Version) => {
+            println!("ve {}", args::VERSION);
+            return Ok(());
+        }
+        Err(e) => {
+            eprint!("ve: {}\n{}", e, args::USAGE);
+            process::exit(2);
+        }
+    };
+    terminal::install_panic_hook();
     let mut editor = Editor::default();
-    if let Some(file_name) = env::args().nth(1) {
-        let path = PathBuf::from(&file_name);
-        editor.open_file(&path)?;
-    }
-    editor.run(&mut stdout)?;
-    stdout.flush()?;
-    let _ = write!(stdout, "{}", ToMainScreen);
-    let _ = write!(stdout, "{}", cursor::Show);
-    Ok(())
+    editor.load_config();
+    editor.open_args(args)?;
+    let guard = TerminalGuard::new()?;
+    let writer = CountingWriter::new(stdout(), editor.output.clone());
+    let mut terminal = Terminal::new(TermionBackend::new(writer))?;
+    // a panic writes the unsaved buffers from the hook; see swap::SNAPSHOT
+    let result = editor.run(&mut terminal);
+    drop(terminal);
+    drop(guard);
+    result
 }
@@ -0,0 +1,29 @@
+use std::fs;
+use std::path::Path;
+use ve::harness::Script;
+
+#[test]
+fn scripts_replay_to_expected_state() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ve"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scripts in {}", dir.display());
+    let mut failed = Vec::new();
+    for path in &paths {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let source = fs::read_to_string(path).unwrap();
+        match Script::parse(&source) {
+            Ok(script) => {
+                for failure in script.failures() {
+                    failed.push(format!("{}: {}", name, failure));
+                }
+            }
+            Err(e) => failed.push(format!("{}: {}", name, e)),
+        }
+    }
+    assert!(failed.is_empty(), "{}", failed.join("\n\n"));
+}